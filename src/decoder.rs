@@ -1,69 +1,86 @@
 use std::collections::HashMap;
-use std::io::Result;
 
+use crate::error::{Error, Result};
+use crate::instruction::{
+    ByteRegister, EffectiveAddress, Opcode, Operand, Repeat, SegmentRegister, Width, WordRegister,
+};
 use crate::instruction_table::{
     generate_instruction_table, Argument, Flag, Instruction, InstructionLookup, Reg, WordField,
 };
 
-const REGISTER_ENCODING: [[&'static str; 8]; 2] = [
-    ["al", "cl", "dl", "bl", "ah", "ch", "dh", "bh"],
-    ["ax", "cx", "dx", "bx", "sp", "bp", "si", "di"],
-];
-
-const MEMORY_ENCODING_BASE: [&'static str; 8] = [
-    "bx + si", "bx + di", "bp + si", "bp + di", "si", "di", "bp", "bx",
-];
 #[repr(u8)]
 #[derive(Debug)]
 enum Mode {
-    MemoryModeNoDisplacement,
-    MemoryModeByteDisplacement,
-    MemoryModeWordDisplacement,
-    RegisterMode,
-    NoMode,
+    MemoryNoDisplacement,
+    MemoryByteDisplacement,
+    MemoryWordDisplacement,
+    Register,
+    Unset,
 }
 impl Mode {
     fn parse(val: u8) -> Mode {
         match val {
-            0 => Mode::MemoryModeNoDisplacement,
-            1 => Mode::MemoryModeByteDisplacement,
-            2 => Mode::MemoryModeWordDisplacement,
-            3 => Mode::RegisterMode,
-            _ => Mode::NoMode,
+            0 => Mode::MemoryNoDisplacement,
+            1 => Mode::MemoryByteDisplacement,
+            2 => Mode::MemoryWordDisplacement,
+            3 => Mode::Register,
+            _ => Mode::Unset,
         }
     }
 }
-fn decode_register(w: usize, reg: usize) -> String {
-    return REGISTER_ENCODING[w][reg].to_string();
-}
-
-fn decode_memory_address(idx: usize, offset: u16) -> String {
-    if offset > 0 {
-        return format!("[{} + {}]", MEMORY_ENCODING_BASE[idx], offset);
+fn decode_register(w: usize, reg: u8) -> Operand {
+    if w == 1 {
+        return Operand::Register(WordRegister::parse(reg));
     }
-
-    return format!("[{}]", MEMORY_ENCODING_BASE[idx]);
+    return Operand::SubRegister(ByteRegister::parse(reg));
 }
 
 const MSB_BITMASKS: [u8; 8] = [
     0b10000000, 0b11000000, 0b11100000, 0b11110000, 0b11111000, 0b11111100, 0b11111110, 0b11111111,
 ];
+fn matches_fixed_bits(instruction: &Instruction, byte: u8) -> bool {
+    // Fixed bits after the flags can still be part of the first byte, so they have to match
+    // as well, e.g. to tell 0x8c and 0x8e apart from 0x8d and 0x8f, or push es (0x06) from pop es (0x07).
+    let mut offset = instruction.offset + instruction.flags.len();
+    for argument in &instruction.extra_args {
+        if offset >= 8 {
+            break;
+        }
+        offset += match argument {
+            Argument::FixedBit(value) => {
+                if get_argument_value(byte, 1, offset as u8) != *value {
+                    return false;
+                }
+                1
+            }
+            Argument::Mode | Argument::SegReg => 2,
+            Argument::Rm | Argument::Reg(_) => 3,
+            _ => break,
+        };
+    }
+    return true;
+}
+
 fn decode_instruction<'a>(
-    instruction_table: &'a [(u8, InstructionLookup<'a>)],
+    instruction_table: &'a [(u8, InstructionLookup)],
     from: &[u8; 2],
-) -> Option<&'a Instruction<'a>> {
+) -> Option<&'a Instruction> {
     for instruction in instruction_table {
         match &instruction.1 {
             InstructionLookup::Instr(ins) => {
-                if (from[0] & MSB_BITMASKS[ins.offset - 1]) == instruction.0 {
-                    return Some(&ins);
+                if (from[0] & MSB_BITMASKS[ins.offset - 1]) == instruction.0
+                    && matches_fixed_bits(ins, from[0])
+                {
+                    return Some(ins);
                 }
             }
             InstructionLookup::MultiInstr(possible_instructions) => {
                 for ins in possible_instructions {
                     let lookup_offset = ins.0.offset;
 
-                    if (from[0] & MSB_BITMASKS[ins.1.offset - 1]) != instruction.0 {
+                    if (from[0] & MSB_BITMASKS[ins.1.offset - 1]) != instruction.0
+                        || !matches_fixed_bits(&ins.1, from[0])
+                    {
                         continue;
                     }
                     // Currently either the last 3 bits in the first byte
@@ -96,296 +113,898 @@ fn decode_flags<'a>(
 ) -> HashMap<&'a Flag, usize> {
     // Due to the ISA specification, we can assume that all flags lie on the first bit
     let mut output = HashMap::new();
-    for idx in 0..flags.len() {
+    for (idx, flag) in flags.iter().enumerate() {
         let offset_byte = byte >> (8 - (offset + idx + 1));
         let masked_byte = offset_byte & 1;
-        output.insert(&flags[idx], masked_byte as usize);
+        output.insert(flag, masked_byte as usize);
     }
 
     return output;
 }
 #[derive(Debug)]
 pub struct DecodedArgument {
-    pub operand: String,
-    pub source: String,
-    pub destination: String,
-    byte_count: usize,
+    pub opcode: Opcode,
+    // Byte offset of the instruction in the decoded stream.
+    pub offset: usize,
+    pub destination: Option<Operand>,
+    pub source: Option<Operand>,
+    pub width: Width,
+    // Includes the prefix bytes.
+    pub byte_count: usize,
+    pub prefix_count: usize,
+    pub repeat: Option<Repeat>,
+    pub segment_override: Option<SegmentRegister>,
+    pub lock: bool,
 }
-impl DecodedArgument {
-    pub fn parse(val: &str) -> Option<DecodedArgument> {
-        // We assume that instructions are made up of 2 or 3 words. 
-        // If they're made up of 3 words, then there is a ',' character between the destination and source.
-        let mut split: Vec<String> = val.split(" ").map(|s| s.to_string()).collect();
-        match split.len() {
-            3 => {
-                return Some(DecodedArgument {
-                    operand: split[0].to_string(),
-                    source: split[2].to_string(),
-                    destination: {
-                        split[1].pop();
-                        split[1].to_string()
-                    },
-                    byte_count: 0,
-                })
-            }
-            2 =>  {
-                return Some(DecodedArgument {
-                    operand: split[0].to_string(),
-                    source: split[1].to_string(),
-                    destination: String::from(""),
-                    byte_count: 0,
-                })
-            }
-            _ => None
-        }
-    }
+
+// `from` starts at the instruction, `offset` is where the instruction is in the whole stream.
+fn read_byte(from: &[u8], idx: usize, offset: usize) -> Result<u8> {
+    return from
+        .get(idx)
+        .copied()
+        .ok_or(Error::TruncatedInstruction { offset });
+}
+
+fn read_word(from: &[u8], idx: usize, offset: usize) -> Result<u16> {
+    let low = read_byte(from, idx, offset)? as u16;
+    let high = read_byte(from, idx + 1, offset)? as u16;
+    return Ok((high << 8) | low);
 }
 
 fn decode_arguments<'a>(
     from: &'a [u8],
+    stream_offset: usize,
     flags: &'a HashMap<&'a Flag, usize>,
     starting_offset: &usize,
-    extra_args: &'a [Argument],
-) -> Result<DecodedArgument> {
-    let mut output = DecodedArgument {
-        operand: String::from(""),
-        source: String::from(""),
-        destination: String::from(""),
-        byte_count: starting_offset / 8,
-    };
+    instruction: &'a Instruction,
+) -> Result<(Vec<Operand>, usize)> {
+    // Operands are collected in the order the instruction table lists them.
+    // The first one is the destination, unless the D flag says otherwise.
+    let mut operands: Vec<Operand> = Vec::new();
 
     let mut offset = *starting_offset;
-    let mut mode = Mode::NoMode;
+    let mut mode = Mode::Unset;
     let mut has_querky_displacement = false;
-    let mut is_reg_explicit = false;
+    // Instructions without a W flag only ever address word registers.
+    let w = *flags.get(&Flag::W).unwrap_or(&1);
 
-    for argument in extra_args {
+    for argument in &instruction.extra_args {
         let source_byte = offset / 8;
         let byte_offset = offset % 8;
+        let current_byte = || read_byte(from, source_byte, stream_offset);
         match argument {
             Argument::Mode => {
-                mode = Mode::parse(get_argument_value(from[source_byte], 2, byte_offset as u8));
+                mode = Mode::parse(get_argument_value(current_byte()?, 2, byte_offset as u8));
                 offset += 2;
             }
             Argument::Rm => {
-                let rm_idx = get_argument_value(from[source_byte], 3, byte_offset as u8) as usize;
+                let rm_idx = get_argument_value(current_byte()?, 3, byte_offset as u8);
                 match mode {
-                    Mode::MemoryModeNoDisplacement => {
+                    Mode::MemoryNoDisplacement => {
                         if rm_idx == 0b110 {
-                            assert!(source_byte + 2 < from.len(), "ERROR: Trying to read immediate field, which is not in instruction stream");
-                            output.source = (((from[source_byte + 2] as u16) << 8)
-                                | (from[source_byte + 1] as u16))
-                                .to_string();
+                            let address = read_word(from, source_byte + 1, stream_offset)?;
+                            operands.push(Operand::Memory(EffectiveAddress::direct(address)));
                             has_querky_displacement = true;
-                        } else if is_reg_explicit {
-                            output.destination = decode_memory_address(rm_idx, 0);
                         } else {
-                            output.source = decode_memory_address(rm_idx, 0);
+                            operands.push(Operand::Memory(EffectiveAddress::parse(rm_idx, 0)));
                         }
                     }
-                    Mode::MemoryModeByteDisplacement => {
-                        assert!(
-                            source_byte + 1 < from.len(),
-                            "ERROR: Trying to read unset displacement byte!"
-                        );
-                        let displacement = (255 as u16) & from[source_byte + 1] as u16;
-                        if is_reg_explicit {
-                            output.destination = decode_memory_address(rm_idx, displacement);
-                        } else {
-                            output.source = decode_memory_address(rm_idx, displacement);
-                        }
+                    Mode::MemoryByteDisplacement => {
+                        let displacement =
+                            read_byte(from, source_byte + 1, stream_offset)? as i8 as i16;
+                        operands.push(Operand::Memory(EffectiveAddress::parse(
+                            rm_idx,
+                            displacement,
+                        )));
                     }
-                    Mode::MemoryModeWordDisplacement => {
-                        assert!(source_byte + 2 < from.len(), "ERROR: Trying to read immediate field, which is not in instruction stream");
-                        let disp_lo = from[source_byte + 1];
-                        let disp_hi = from[source_byte + 2];
-                        let displacement = ((disp_hi as u16) << 8) | (disp_lo as u16);
-                        if is_reg_explicit {
-                            output.destination = decode_memory_address(rm_idx, displacement);
-                        } else {
-                            output.source = decode_memory_address(rm_idx, displacement);
-                        }
+                    Mode::MemoryWordDisplacement => {
+                        let displacement = read_word(from, source_byte + 1, stream_offset)? as i16;
+                        operands.push(Operand::Memory(EffectiveAddress::parse(
+                            rm_idx,
+                            displacement,
+                        )));
                     }
-                    Mode::RegisterMode => {
-                        let w = flags.get(&Flag::W);
-                        match w {
-                            Some(w) => {
-                                if is_reg_explicit {
-                                    output.destination = decode_register(*w, rm_idx);
-                                } else {
-                                    output.source = decode_register(*w, rm_idx);
-                                }
-                            }
-                            None => {
-                                panic!("ERROR: Trying to decode reg field without w flag being set")
-                            }
-                        }
+                    Mode::Register if instruction.opcode.needs_memory_operand() => {
+                        return Err(Error::InvalidOperand {
+                            offset: stream_offset,
+                            opcode: instruction.opcode,
+                        })
                     }
-                    Mode::NoMode => {
-                        panic!("ERROR: Trying to decode RM field without Mode being set.")
+                    Mode::Register => operands.push(decode_register(w, rm_idx)),
+                    Mode::Unset => {
+                        return Err(Error::InvalidOperand {
+                            offset: stream_offset,
+                            opcode: instruction.opcode,
+                        })
                     }
                 }
                 offset += 3;
             }
             Argument::Reg(reg) => match reg {
                 Reg::Implicit => {
-                    let w = flags.get(&Flag::W);
-                    match w {
-                        Some(w) => {
-                            let reg = (get_argument_value(from[source_byte], 3, byte_offset as u8))
-                                as usize;
-                            output.destination = decode_register(*w, reg);
-                        }
-                        None => {
-                            panic!("ERROR: Trying to decode reg field without w flag being set")
-                        }
-                    }
+                    let reg = get_argument_value(current_byte()?, 3, byte_offset as u8);
+                    operands.push(decode_register(w, reg));
                     offset += 3;
                 }
-                Reg::Explicit(_) => {
-                    is_reg_explicit = true;
+                Reg::Explicit(value) => {
+                    // The reg field extends the opcode, other values are different instructions.
+                    if get_argument_value(current_byte()?, 3, byte_offset as u8) != *value {
+                        return Err(Error::UnknownOpcode {
+                            offset: stream_offset,
+                            byte: read_byte(from, 0, stream_offset)?,
+                        });
+                    }
                     offset += 3;
                 }
             },
             Argument::Word(data) => match data {
                 WordField::Data => {
-                    let w = flags.get(&Flag::W);
-                    match w {
-                        Some(w) => {
-                            let s = flags.get(&Flag::S);
-                            match s {
-                                Some(s) => {
-                                    if *w == 1 && *s == 0 {
-                                        assert!(source_byte + 1 < from.len(), "ERROR: Trying to read an immediate field which is not in instruction stream");
-
-                                        let data_lo = from[source_byte];
-                                        let data_hi = from[source_byte + 1];
-                                        let displacement =
-                                            ((data_hi as u16) << 8) | (data_lo as u16);
-
-                                        output.source = displacement.to_string();
-                                        offset += 16;
-                                    } else if *s == 1 {
-                                        assert!(source_byte < from.len(), "ERROR: Trying to read an immediate field which is not in instruction stream");
-
-                                        if from[source_byte] >= 128 {
-                                            output.source = (0b1111111100000000
-                                                | from[source_byte] as u16)
-                                                .to_string();
-                                        } else {
-                                            //
-                                            output.source = (from[source_byte] as u16).to_string();
-                                        }
-                                        offset += 8;
-                                    } else {
-                                        assert!(source_byte < from.len(), "ERROR: Trying to read an immediate field which is not in instruction stream");
-
-                                        output.source = from[source_byte].to_string();
-                                        offset += 8;
-                                    }
-                                }
-                                None => {
-                                    if *w == 1 {
-                                        assert!(source_byte + 2 < from.len(), "ERROR: Trying to read an immediate field which is not in instruction stream");
-                                        let data_lo = from[source_byte];
-                                        let data_hi = from[source_byte + 1];
-                                        let displacement =
-                                            ((data_hi as u16) << 8) | (data_lo as u16);
-                                        output.source = displacement.to_string();
-                                        offset += 16;
-                                    } else {
-                                        output.source = from[source_byte].to_string();
-                                        offset += 8;
-                                    }
-                                }
-                            }
+                    match (w, flags.get(&Flag::S)) {
+                        (1, Some(0)) | (1, None) => {
+                            let data = read_word(from, source_byte, stream_offset)?;
+                            operands.push(Operand::Immediate(data));
+                            offset += 16;
+                        }
+                        (1, Some(_)) => {
+                            // Sign extended to a word
+                            let data = read_byte(from, source_byte, stream_offset)? as i8 as i16;
+                            operands.push(Operand::Immediate(data as u16));
+                            offset += 8;
                         }
-                        None => {
-                            output.source = from[source_byte].to_string();
+                        _ => {
+                            let data = read_byte(from, source_byte, stream_offset)?;
+                            operands.push(Operand::Immediate(data as u16));
                             offset += 8;
                         }
                     }
                 }
                 WordField::Disp => match mode {
-                    Mode::MemoryModeByteDisplacement => {
+                    Mode::MemoryByteDisplacement => {
                         offset += 8;
                     }
-                    Mode::MemoryModeWordDisplacement => {
+                    Mode::MemoryWordDisplacement => {
                         offset += 16;
                     }
-                    Mode::MemoryModeNoDisplacement => {
-                        if has_querky_displacement {
-                            offset += 16;
-                        }
+                    Mode::MemoryNoDisplacement if has_querky_displacement => {
+                        offset += 16;
                     }
                     _ => {}
                 },
-                _ => {
+                WordField::Addr => {
+                    let address = read_word(from, source_byte, stream_offset)?;
+                    operands.push(Operand::Memory(EffectiveAddress::direct(address)));
+                    offset += 16;
+                }
+                WordField::Rel => {
+                    let displacement = read_word(from, source_byte, stream_offset)? as i16;
+                    operands.push(Operand::Relative(displacement));
                     offset += 16;
                 }
             },
-            Argument::SegReg => offset += 2,
-            Argument::FixedBit(_) => offset += 1,
+            Argument::Pointer => {
+                let address = read_word(from, source_byte, stream_offset)?;
+                let segment = read_word(from, source_byte + 2, stream_offset)?;
+                operands.push(Operand::Far {
+                    segment,
+                    offset: address,
+                });
+                offset += 32;
+            }
+            Argument::SegReg => {
+                let sr = get_argument_value(current_byte()?, 2, byte_offset as u8);
+                operands.push(Operand::Segment(SegmentRegister::parse(sr)));
+                offset += 2;
+            }
+            Argument::FixedBit(value) => {
+                // Fixed bits past the first byte, like the high bit of a segment register field.
+                if get_argument_value(current_byte()?, 1, byte_offset as u8) != *value {
+                    return Err(Error::InvalidOperand {
+                        offset: stream_offset,
+                        opcode: instruction.opcode,
+                    });
+                }
+                offset += 1;
+            }
             Argument::Byte => {
-                output.destination = from[source_byte].to_string();
+                let displacement = read_byte(from, source_byte, stream_offset)? as i8 as i16;
+                operands.push(Operand::Relative(displacement));
                 offset += 8;
             }
+            Argument::ByteData => {
+                let data = read_byte(from, source_byte, stream_offset)?;
+                operands.push(Operand::Immediate(data as u16));
+                offset += 8;
+            }
+            Argument::Accumulator => operands.push(decode_register(w, 0)),
+            Argument::Dx => operands.push(Operand::Register(WordRegister::Dx)),
+            Argument::Count => match flags.get(&Flag::V) {
+                Some(1) => operands.push(Operand::SubRegister(ByteRegister::Cl)),
+                _ => operands.push(Operand::Immediate(1)),
+            },
         }
     }
 
-    if output.destination == "" {
-        let w = flags.get(&Flag::W);
-        match w {
-            Some(w) => {
-                if *w == 1 {
-                    output.destination = "ax".to_string();
-                } else {
-                    output.destination = "al".to_string();
-                }
-            }
-            None => (),
+    return Ok((operands, offset / 8));
+}
+
+fn decode_width(flags: &HashMap<&Flag, usize>, operands: &[Operand]) -> Width {
+    if let Some(w) = flags.get(&Flag::W) {
+        if *w == 1 {
+            return Width::Word;
         }
+        return Width::Byte;
     }
-    output.byte_count = offset / 8;
-    return Ok(output);
+    if operands
+        .iter()
+        .any(|operand| matches!(operand, Operand::SubRegister(_)))
+    {
+        return Width::Byte;
+    }
+    return Width::Word;
 }
-pub fn decode_bitstream(input: Vec<u8>) -> Result<Vec<DecodedArgument>> {
+
+/// Decodes the single instruction starting at `idx` in the given byte stream.
+/// Prefixes are decoded along with the instruction they modify.
+pub(crate) fn decode_next(
+    instruction_table: &[(u8, InstructionLookup)],
+    input: &[u8],
+    idx: usize,
+) -> Result<DecodedArgument> {
+    let mut repeat = None;
+    let mut segment_override = None;
+    let mut lock = false;
+    let mut start = idx;
+    let first_byte = loop {
+        let byte = read_byte(input, start, idx)?;
+        match byte {
+            0xf0 => lock = true,
+            0xf2 => repeat = Some(Repeat::Repne),
+            0xf3 => repeat = Some(Repeat::Rep),
+            // 0x26, 0x2e, 0x36 and 0x3e carry the segment register in bits 3 and 4.
+            0x26 | 0x2e | 0x36 | 0x3e => segment_override = Some(SegmentRegister::parse(byte >> 3)),
+            _ => break byte,
+        }
+        start += 1;
+    };
+    let prefix_count = start - idx;
+
+    // The lookup might need the second byte. If the stream ends, we pad it with zeroes.
+    let lookup_bytes = [first_byte, *input.get(start + 1).unwrap_or(&0)];
+    let instruction =
+        decode_instruction(instruction_table, &lookup_bytes).ok_or(Error::UnknownOpcode {
+            offset: idx,
+            byte: first_byte,
+        })?;
+    // Flags are only read from the first byte.
+    if instruction.offset + instruction.flags.len() > 8 {
+        return Err(Error::UnknownOpcode {
+            offset: idx,
+            byte: first_byte,
+        });
+    }
+
+    let flags = decode_flags(&lookup_bytes[0], &instruction.offset, &instruction.flags);
+    let last_byte = std::cmp::min(start + instruction.max_byte_count + 1, input.len());
+    let (mut operands, byte_count) = decode_arguments(
+        &input[start..last_byte],
+        idx,
+        &flags,
+        &(instruction.offset + flags.len()),
+        instruction,
+    )?;
+    for operand in operands.iter_mut() {
+        if let Operand::Memory(address) = operand {
+            address.segment = segment_override;
+        }
+    }
+
+    let mut destination = operands.first().copied();
+    let mut source = operands.get(1).copied();
+    if let Some(d) = flags.get(&Flag::D) {
+        if *d == 0 {
+            std::mem::swap(&mut source, &mut destination);
+        }
+    }
+
+    return Ok(DecodedArgument {
+        opcode: instruction.opcode,
+        offset: idx,
+        destination,
+        source,
+        width: decode_width(&flags, &operands),
+        byte_count: prefix_count + byte_count,
+        prefix_count,
+        repeat,
+        segment_override,
+        lock,
+    });
+}
+
+/// Decodes a whole byte stream. Fails on the first byte that doesn't start a known instruction.
+pub fn decode_bitstream(input: &[u8]) -> Result<Vec<DecodedArgument>> {
     let instruction_table = generate_instruction_table();
     let mut output: Vec<DecodedArgument> = Vec::from([]);
     let mut idx = 0;
-    while idx < input.len() - 1 {
-        let lookup_bytes = [input[idx], input[idx + 1]];
-        match decode_instruction(&instruction_table, &lookup_bytes) {
-            None => {
-                idx += 1;
-            }
-            Some(instruction) => {
-                assert!(
-                    instruction.offset + instruction.flags.len() <= 8,
-                    "Flags can't bleed into the second byte!"
-                );
-
-                let flags = decode_flags(&lookup_bytes[0], &instruction.offset, &instruction.flags);
-                let last_byte = std::cmp::min(idx + instruction.max_byte_count + 1, input.len());
-                let mut arguments = decode_arguments(
-                    &input[idx..last_byte],
-                    &flags,
-                    &(instruction.offset + flags.len()),
-                    &instruction.extra_args,
-                )?;
-                if let Some(d) = flags.get(&Flag::D) {
-                    if *d == 0 {
-                        let temp = arguments.source;
-                        arguments.source = arguments.destination;
-                        arguments.destination = temp;
-                    }
+    while idx < input.len() {
+        let arguments = decode_next(&instruction_table, input, idx)?;
+        idx += arguments.byte_count;
+        output.push(arguments);
+    }
+    return Ok(output);
+}
+
+// The stream offset a relative jump, call or loop continues at.
+fn relative_target(instruction: &DecodedArgument) -> Option<i64> {
+    match instruction.destination {
+        Some(Operand::Relative(displacement)) => {
+            Some((instruction.offset + instruction.byte_count) as i64 + displacement as i64)
+        }
+        _ => None,
+    }
+}
+
+/// Disassembles a whole byte stream into assembly lines.
+/// Relative targets get a `label_N:` line, numbered in the order they appear in the stream.
+/// Targets outside the stream, or inside another instruction, are written relative to `$`.
+/// Relative jumps, calls and loops assemble back into the same bytes. Other instructions give
+/// the same instruction, but maybe in a shorter encoding, like `[bx]` for `[bx + 0]`.
+pub fn disassemble(input: &[u8]) -> Result<Vec<String>> {
+    let instructions = decode_bitstream(input)?;
+
+    // The first pass collects the targets, which only count when an instruction starts there
+    // or the stream ends there.
+    let mut labels: Vec<i64> = instructions
+        .iter()
+        .filter_map(relative_target)
+        .filter(|target| {
+            *target == input.len() as i64
+                || instructions
+                    .iter()
+                    .any(|instruction| instruction.offset as i64 == *target)
+        })
+        .collect();
+    labels.sort_unstable();
+    labels.dedup();
+
+    let mut output = Vec::new();
+    for instruction in &instructions {
+        if let Ok(label) = labels.binary_search(&(instruction.offset as i64)) {
+            output.push(format!("label_{}:", label));
+        }
+        let line = match relative_target(instruction) {
+            Some(target) => match labels.binary_search(&target) {
+                Ok(label) => instruction.to_string_with_target(&format!("label_{}", label)),
+                Err(_) => {
+                    let distance = target - instruction.offset as i64;
+                    instruction.to_string_with_target(&format!("${:+}", distance))
                 }
+            },
+            None => instruction.to_string(),
+        };
+        output.push(line);
+    }
+    if labels.last() == Some(&(input.len() as i64)) {
+        output.push(format!("label_{}:", labels.len() - 1));
+    }
+    return Ok(output);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_to_strings(code: &[u8]) -> Vec<String> {
+        return decode_bitstream(code)
+            .unwrap()
+            .iter()
+            .map(|instruction| instruction.to_string())
+            .collect();
+    }
+
+    #[test]
+    fn decode_errors_carry_the_offset() {
+        // mov cx, 3 followed by a mov with its immediate cut off
+        let truncated = [0xb9, 0x03, 0x00, 0xbb, 0x03];
+        assert_eq!(
+            decode_bitstream(&truncated).unwrap_err(),
+            Error::TruncatedInstruction { offset: 3 }
+        );
+        // A mov with its ModRM byte cut off
+        assert_eq!(
+            decode_bitstream(&[0x89]).unwrap_err(),
+            Error::TruncatedInstruction { offset: 0 }
+        );
 
-                arguments.operand = instruction.operand.to_string();
-                idx += &arguments.byte_count;
-                output.push(arguments);
+        let unknown = [0xb9, 0x03, 0x00, 0xd6];
+        assert_eq!(
+            decode_bitstream(&unknown).unwrap_err(),
+            Error::UnknownOpcode {
+                offset: 3,
+                byte: 0xd6
             }
+        );
+    }
+
+    #[test]
+    fn errors_after_prefixes_point_at_the_first_prefix() {
+        assert_eq!(
+            decode_bitstream(&[0x90, 0xf3, 0x2e, 0xd6]).unwrap_err(),
+            Error::UnknownOpcode {
+                offset: 1,
+                byte: 0xd6
+            }
+        );
+        assert_eq!(
+            decode_bitstream(&[0x90, 0xf3, 0x89]).unwrap_err(),
+            Error::TruncatedInstruction { offset: 1 }
+        );
+    }
+
+    #[test]
+    fn invalid_fields_are_errors() {
+        // mov r/m, imm only exists with a reg field of 0.
+        assert_eq!(
+            decode_bitstream(&[0xc6, 0x08, 0x00]).unwrap_err(),
+            Error::UnknownOpcode {
+                offset: 0,
+                byte: 0xc6
+            }
+        );
+        // There is no fifth segment register.
+        assert_eq!(
+            decode_bitstream(&[0xb9, 0x03, 0x00, 0x8c, 0xe0]).unwrap_err(),
+            Error::InvalidOperand {
+                offset: 3,
+                opcode: Opcode::Mov
+            }
+        );
+    }
+
+    #[test]
+    fn far_transfers_need_a_memory_operand() {
+        // call ax would be ff d0, a near call.
+        assert_eq!(
+            decode_bitstream(&[0xff, 0xd8]).unwrap_err(),
+            Error::InvalidOperand {
+                offset: 0,
+                opcode: Opcode::CallFar
+            }
+        );
+        // jmp ax would be ff e0, a near jump.
+        assert_eq!(
+            decode_bitstream(&[0xff, 0xe8]).unwrap_err(),
+            Error::InvalidOperand {
+                offset: 0,
+                opcode: Opcode::JmpFar
+            }
+        );
+    }
+
+    #[test]
+    fn loading_addresses_needs_a_memory_operand() {
+        for (code, opcode) in [
+            ([0x8d, 0xc0], Opcode::Lea), // lea ax, ax
+            ([0xc5, 0xc0], Opcode::Lds), // lds ax, ax
+            ([0xc4, 0xc0], Opcode::Les), // les ax, ax
+        ] {
+            assert_eq!(
+                decode_bitstream(&code).unwrap_err(),
+                Error::InvalidOperand { offset: 0, opcode }
+            );
         }
     }
-    return Ok(output);
+
+    #[test]
+    fn decode_logical_instructions() {
+        let code = [
+            0x20, 0xd8, // and al, bl
+            0x81, 0xe1, 0xff, 0x00, // and cx, 255
+            0x0c, 0x0f, // or al, 15
+            0x33, 0x07, // xor ax, [bx]
+            0x85, 0xc3, // test ax, bx
+            0xa8, 0x01, // test al, 1
+            0xf6, 0x07, 0x10, // test byte [bx], 16
+            0xf7, 0xd0, // not ax
+            0xf7, 0x1f, // neg word [bx]
+        ];
+        let decoded = decode_to_strings(&code);
+        assert_eq!(
+            decoded,
+            [
+                "and al, bl",
+                "and cx, 255",
+                "or al, 15",
+                "xor ax, [bx]",
+                "test ax, bx",
+                "test al, 1",
+                "test byte [bx], 16",
+                "not ax",
+                "neg word [bx]",
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_shifts_and_rotates() {
+        let code = [
+            0xd1, 0xe0, // shl ax, 1
+            0xd3, 0xe8, // shr ax, cl
+            0xd0, 0xf8, // sar al, 1
+            0xd3, 0x07, // rol word [bx], cl
+            0xd0, 0x0f, // ror byte [bx], 1
+            0xd1, 0xd3, // rcl bx, 1
+            0xd2, 0xdb, // rcr bl, cl
+        ];
+        let decoded = decode_to_strings(&code);
+        assert_eq!(
+            decoded,
+            [
+                "shl ax, 1",
+                "shr ax, cl",
+                "sar al, 1",
+                "rol word [bx], cl",
+                "ror byte [bx], 1",
+                "rcl bx, 1",
+                "rcr bl, cl",
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_multiply_and_divide() {
+        let code = [
+            0xf7, 0xe3, // mul bx
+            0xf6, 0x2c, // imul byte [si]
+            0xf6, 0xf1, // div cl
+            0xf7, 0x7e, 0x02, // idiv word [bp + 2]
+        ];
+        let decoded = decode_to_strings(&code);
+        assert_eq!(
+            decoded,
+            ["mul bx", "imul byte [si]", "div cl", "idiv word [bp + 2]"]
+        );
+    }
+
+    #[test]
+    fn decode_stack_instructions() {
+        let code = [
+            0x50, // push ax
+            0x5b, // pop bx
+            0x0e, // push cs
+            0x1f, // pop ds
+            0xff, 0x36, 0x00, 0x10, // push word [4096]
+            0x8f, 0x07, // pop word [bx]
+            0x9c, // pushf
+            0x9d, // popf
+            0x01, 0xd8, // add ax, bx
+        ];
+        let decoded = decode_to_strings(&code);
+        assert_eq!(
+            decoded,
+            [
+                "push ax",
+                "pop bx",
+                "push cs",
+                "pop ds",
+                "push word [4096]",
+                "pop word [bx]",
+                "pushf",
+                "popf",
+                "add ax, bx",
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_calls_and_returns() {
+        let code = [
+            0xe8, 0x10, 0x00, // call 16
+            0xff, 0xd3, // call bx
+            0xff, 0x17, // call word [bx]
+            0x9a, 0x34, 0x12, 0x00, 0x10, // call 4096:4660
+            0xff, 0x1f, // call far [bx]
+            0xc3, // ret
+            0xc2, 0x04, 0x00, // ret 4
+            0xcb, // retf
+            0xca, 0x02, 0x00, // retf 2
+        ];
+        let decoded = decode_to_strings(&code);
+        assert_eq!(
+            decoded,
+            [
+                "call 16",
+                "call bx",
+                "call word [bx]",
+                "call 4096:4660",
+                "call far [bx]",
+                "ret",
+                "ret 4",
+                "retf",
+                "retf 2",
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_unconditional_jumps() {
+        let code = [
+            0xeb, 0xfe, // jmp -2
+            0xe9, 0x00, 0x01, // jmp 256
+            0xea, 0x00, 0x00, 0x00, 0x02, // jmp 512:0
+            0xff, 0xe3, // jmp bx
+            0xff, 0x27, // jmp word [bx]
+            0xff, 0x2f, // jmp far [bx]
+        ];
+        let decoded = decode_to_strings(&code);
+        assert_eq!(
+            decoded,
+            [
+                "jmp -2",
+                "jmp 256",
+                "jmp 512:0",
+                "jmp bx",
+                "jmp word [bx]",
+                "jmp far [bx]",
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_string_instructions() {
+        let code = [
+            0xa4, // movsb
+            0xa5, // movsw
+            0xf3, 0xa4, // rep movsb
+            0xf3, 0xa6, // repe cmpsb
+            0xf2, 0xaf, // repne scasw
+            0xf3, 0xab, // rep stosw
+            0xac, // lodsb
+        ];
+        let decoded = decode_to_strings(&code);
+        assert_eq!(
+            decoded,
+            [
+                "movsb",
+                "movsw",
+                "rep movsb",
+                "repe cmpsb",
+                "repne scasw",
+                "rep stosw",
+                "lodsb",
+            ]
+        );
+
+        let decoded = decode_bitstream(&[0xf3, 0xa4]).unwrap();
+        assert_eq!(decoded[0].byte_count, 2);
+        assert!(matches!(
+            decode_bitstream(&[0xa4, 0xf3]),
+            Err(Error::TruncatedInstruction { offset: 1 })
+        ));
+    }
+
+    #[test]
+    fn decode_segment_overrides() {
+        let code = [
+            0x26, 0x8b, 0x00, // mov ax, es:[bx + si]
+            0x2e, 0xa1, 0x00, 0x10, // mov ax, cs:[4096]
+            0x36, 0xc6, 0x07, 0x05, // mov byte ss:[bx], 5
+            0x3e, 0x89, 0x56, 0xfe, // mov ds:[bp - 2], dx
+            0x2e, 0xa4, // cs movsb
+        ];
+        let decoded = decode_to_strings(&code);
+        assert_eq!(
+            decoded,
+            [
+                "mov ax, es:[bx + si]",
+                "mov ax, cs:[4096]",
+                "mov byte ss:[bx], 5",
+                "mov ds:[bp - 2], dx",
+                "cs movsb",
+            ]
+        );
+    }
+
+    #[test]
+    fn segment_overrides_without_memory_operands_are_kept() {
+        let code = [
+            0x2e, 0x90, // cs nop
+            0x26, 0x40, // es inc ax
+            0x36, 0xeb, 0xf9, // ss jmp label_0
+        ];
+        let lines = disassemble(&code).unwrap();
+        assert_eq!(lines, ["label_0:", "cs nop", "es inc ax", "ss jmp label_0"]);
+        // Every byte, prefixes included, is accounted for by some line of the output.
+        let decoded = decode_bitstream(&code).unwrap();
+        let byte_count: usize = decoded.iter().map(|instr| instr.byte_count).sum();
+        assert_eq!(byte_count, code.len());
+        assert!(decoded.iter().all(|instr| instr.segment_override.is_some()));
+    }
+
+    #[test]
+    fn decode_data_transfers() {
+        let code = [
+            0x87, 0x0f, // xchg cx, [bx]
+            0x86, 0xc4, // xchg al, ah
+            0x93, // xchg ax, bx
+            0x8d, 0x40, 0x04, // lea ax, [bx + si + 4]
+            0xc5, 0x36, 0x00, 0x10, // lds si, [4096]
+            0xc4, 0x7e, 0x02, // les di, [bp + 2]
+            0xd7, // xlat
+            0x9f, // lahf
+            0x9e, // sahf
+            0x98, // cbw
+            0x99, // cwd
+        ];
+        let decoded = decode_to_strings(&code);
+        assert_eq!(
+            decoded,
+            [
+                "xchg cx, [bx]",
+                "xchg al, ah",
+                "xchg ax, bx",
+                "lea ax, [bx + si + 4]",
+                "lds si, [4096]",
+                "les di, [bp + 2]",
+                "xlat",
+                "lahf",
+                "sahf",
+                "cbw",
+                "cwd",
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_increments_and_carries() {
+        let code = [
+            0x40, // inc ax
+            0x4f, // dec di
+            0xfe, 0xc3, // inc bl
+            0xff, 0x0f, // dec word [bx]
+            0xfe, 0x47, 0x02, // inc byte [bx + 2]
+            0x11, 0xd8, // adc ax, bx
+            0x1a, 0x0e, 0x00, 0x10, // sbb cl, [4096]
+            0x15, 0x01, 0x00, // adc ax, 1
+            0x1c, 0x02, // sbb al, 2
+            0x83, 0xd1, 0x00, // adc cx, 0
+            0x80, 0x1f, 0x05, // sbb byte [bx], 5
+        ];
+        let decoded = decode_to_strings(&code);
+        assert_eq!(
+            decoded,
+            [
+                "inc ax",
+                "dec di",
+                "inc bl",
+                "dec word [bx]",
+                "inc byte [bx + 2]",
+                "adc ax, bx",
+                "sbb cl, [4096]",
+                "adc ax, 1",
+                "sbb al, 2",
+                "adc cx, 0",
+                "sbb byte [bx], 5",
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_interrupts() {
+        let code = [
+            0xcd, 0x21, // int 33
+            0xcc, // int3
+            0xce, // into
+            0xcf, // iret
+        ];
+        let decoded = decode_to_strings(&code);
+        assert_eq!(decoded, ["int 33", "int3", "into", "iret"]);
+    }
+
+    #[test]
+    fn decode_port_io() {
+        let code = [
+            0xe4, 0x60, // in al, 96
+            0xe5, 0x40, // in ax, 64
+            0xec, // in al, dx
+            0xed, // in ax, dx
+            0xe6, 0x61, // out 97, al
+            0xe7, 0x42, // out 66, ax
+            0xee, // out dx, al
+            0xef, // out dx, ax
+        ];
+        let decoded = decode_to_strings(&code);
+        assert_eq!(
+            decoded,
+            [
+                "in al, 96",
+                "in ax, 64",
+                "in al, dx",
+                "in ax, dx",
+                "out 97, al",
+                "out 66, ax",
+                "out dx, al",
+                "out dx, ax",
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_processor_control() {
+        let code = [
+            0xf8, // clc
+            0xf9, // stc
+            0xf5, // cmc
+            0xfc, // cld
+            0xfd, // std
+            0xfa, // cli
+            0xfb, // sti
+            0x90, // nop
+            0x9b, // wait
+            0xf0, 0x87, 0x07, // lock xchg ax, [bx]
+            0xf4, // hlt
+        ];
+        let decoded = decode_to_strings(&code);
+        assert_eq!(
+            decoded,
+            [
+                "clc",
+                "stc",
+                "cmc",
+                "cld",
+                "std",
+                "cli",
+                "sti",
+                "nop",
+                "wait",
+                "lock xchg ax, [bx]",
+                "hlt",
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_decimal_adjust() {
+        let code = [
+            0x27, // daa
+            0x2f, // das
+            0x37, // aaa
+            0x3f, // aas
+            0xd4, 0x0a, // aam 10
+            0xd5, 0x10, // aad 16
+        ];
+        let decoded = decode_to_strings(&code);
+        assert_eq!(decoded, ["daa", "das", "aaa", "aas", "aam 10", "aad 16"]);
+    }
+
+    #[test]
+    fn disassembly_with_labels() {
+        let code = [
+            0xb9, 0x03, 0x00, // mov cx, 3
+            0x49, // label_0: dec cx
+            0x75, 0x03, // jne label_1
+            0xe9, 0x04, 0x00, // jmp near label_2
+            0xe2, 0xf8, // label_1: loop label_0
+            0xeb, 0xf4, // jmp $-10
+            0xe8, 0xf1, 0xff, // label_2: call $-12
+            0x74, 0x00, // je label_3
+        ];
+        let lines = disassemble(&code).unwrap();
+        assert_eq!(
+            lines,
+            [
+                "mov cx, 3",
+                "label_0:",
+                "dec cx",
+                "jne label_1",
+                "jmp near label_2",
+                "label_1:",
+                "loop label_0",
+                "jmp $-10",
+                "label_2:",
+                "call $-12",
+                "je label_3",
+                "label_3:",
+            ]
+        );
+    }
 }
@@ -0,0 +1,229 @@
+use std::fmt;
+
+use crate::decoder::DecodedArgument;
+use crate::error::Error;
+use crate::flags::Flags;
+use crate::instruction::{
+    ByteRegister, EffectiveAddress, Opcode, Operand, Repeat, SegmentRegister, Width, WordRegister,
+};
+use crate::io::PortAccess;
+use crate::simulator::{HaltReason, MAX_STEPS};
+
+// Display order and letters of the flags, lowest bit first.
+const FLAG_LETTERS: [(u16, char); 9] = [
+    (Flags::CARRY, 'C'),
+    (Flags::PARITY, 'P'),
+    (Flags::AUXILIARY_CARRY, 'A'),
+    (Flags::ZERO, 'Z'),
+    (Flags::SIGN, 'S'),
+    (Flags::TRAP, 'T'),
+    (Flags::INTERRUPT, 'I'),
+    (Flags::DIRECTION, 'D'),
+    (Flags::OVERFLOW, 'O'),
+];
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.mnemonic())
+    }
+}
+
+impl fmt::Display for WordRegister {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl fmt::Display for ByteRegister {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl fmt::Display for SegmentRegister {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl fmt::Display for Width {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Width::Byte => write!(f, "byte"),
+            Width::Word => write!(f, "word"),
+        }
+    }
+}
+
+impl fmt::Display for HaltReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HaltReason::EndOfProgram => write!(f, "end of program"),
+            HaltReason::Hlt => write!(f, "hlt"),
+            HaltReason::StepLimit => write!(f, "step limit of {} instructions", MAX_STEPS),
+            HaltReason::UnhandledInterrupt(vector) => {
+                write!(f, "unhandled interrupt {:x}", vector)
+            }
+        }
+    }
+}
+
+impl fmt::Display for PortAccess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PortAccess::Read { port, width } => write!(f, "in {} from port {:x}", width, port),
+            PortAccess::Write { port, width, value } => {
+                write!(f, "out {} {:x} to port {:x}", width, value, port)
+            }
+        }
+    }
+}
+
+impl fmt::Display for EffectiveAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(segment) = self.segment {
+            write!(f, "{}:", segment)?;
+        }
+        if self.is_direct() {
+            return write!(f, "[{}]", self.displacement as u16);
+        }
+
+        let registers: Vec<&str> = [self.base, self.index]
+            .iter()
+            .flatten()
+            .map(|reg| reg.name())
+            .collect();
+        write!(f, "[{}", registers.join(" + "))?;
+        if self.displacement > 0 {
+            write!(f, " + {}", self.displacement)?;
+        } else if self.displacement < 0 {
+            write!(f, " - {}", -(self.displacement as i32))?;
+        }
+        write!(f, "]")
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Register(reg) => write!(f, "{}", reg),
+            Operand::SubRegister(reg) => write!(f, "{}", reg),
+            Operand::Segment(reg) => write!(f, "{}", reg),
+            Operand::Immediate(value) => write!(f, "{}", *value as i16),
+            Operand::Memory(address) => write!(f, "{}", address),
+            Operand::Relative(displacement) => write!(f, "{}", displacement),
+            Operand::Far { segment, offset } => write!(f, "{}:{}", segment, offset),
+        }
+    }
+}
+
+impl DecodedArgument {
+    // Prefixes, mnemonic and the width suffix of string instructions.
+    fn write_mnemonic(&self, f: &mut impl fmt::Write) -> fmt::Result {
+        if self.lock {
+            write!(f, "lock ")?;
+        }
+        match (self.repeat, self.opcode) {
+            (Some(Repeat::Rep), Opcode::Cmps | Opcode::Scas) => write!(f, "repe ")?,
+            (Some(Repeat::Rep), _) => write!(f, "rep ")?,
+            (Some(Repeat::Repne), _) => write!(f, "repne ")?,
+            (None, _) => {}
+        }
+        // Memory operands show their override themselves. Without one, like on string
+        // instructions, it's printed as a prefix so it isn't lost.
+        let has_memory_operand = [self.destination, self.source]
+            .iter()
+            .flatten()
+            .any(|operand| matches!(operand, Operand::Memory(_)));
+        if let (Some(segment), false) = (self.segment_override, has_memory_operand) {
+            write!(f, "{} ", segment)?;
+        }
+        write!(f, "{}", self.opcode)?;
+        // String instructions don't have operands, their suffix gives the width.
+        if self.opcode.is_string() {
+            match self.width {
+                Width::Byte => write!(f, "b")?,
+                Width::Word => write!(f, "w")?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints a jump, call or loop with `target` in place of its relative displacement.
+    pub(crate) fn to_string_with_target(&self, target: &str) -> String {
+        let mut output = String::new();
+        // Writing to a String can't fail.
+        let _ = self.write_mnemonic(&mut output);
+        // Assemblers shorten jmp to the 2 byte form when they can, unless told otherwise.
+        if self.opcode == Opcode::Jmp && self.byte_count - self.prefix_count == 3 {
+            output.push_str(" near");
+        }
+        output.push(' ');
+        output.push_str(target);
+        return output;
+    }
+}
+
+impl fmt::Display for DecodedArgument {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_mnemonic(f)?;
+
+        // Without a register operand, the assembler can't tell the width of a memory operand.
+        // The cl count of a shift doesn't tell it either.
+        let needs_width = self.opcode.is_shift()
+            || [self.destination, self.source]
+                .iter()
+                .flatten()
+                .all(|operand| matches!(operand, Operand::Memory(_) | Operand::Immediate(_)));
+        let operands = [self.destination, self.source];
+        for (idx, operand) in operands.iter().flatten().enumerate() {
+            if idx == 0 {
+                write!(f, " ")?;
+            } else {
+                write!(f, ", ")?;
+            }
+            if self.opcode.is_far() && matches!(operand, Operand::Memory(_)) {
+                write!(f, "far ")?;
+            } else if needs_width && matches!(operand, Operand::Memory(_)) {
+                write!(f, "{} ", self.width)?;
+            }
+            write!(f, "{}", operand)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (flag, letter) in FLAG_LETTERS {
+            if self.get(flag) {
+                write!(f, "{}", letter)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::TruncatedInstruction { offset } => {
+                write!(f, "truncated instruction at offset {:x}", offset)
+            }
+            Error::UnknownOpcode { offset, byte } => {
+                write!(f, "unknown opcode byte {:02x} at offset {:x}", byte, offset)
+            }
+            Error::InvalidOperand { offset, opcode } => {
+                write!(f, "invalid operand for {} at offset {:x}", opcode, offset)
+            }
+            Error::UnsupportedInstruction { offset, opcode } => {
+                write!(
+                    f,
+                    "unsupported instruction {} at offset {:x}",
+                    opcode, offset
+                )
+            }
+            Error::Halted { offset } => write!(f, "cpu is halted at offset {:x}", offset),
+        }
+    }
+}
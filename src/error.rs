@@ -0,0 +1,47 @@
+use crate::instruction::Opcode;
+
+/// Errors while decoding or executing machine code. All of them carry the byte offset
+/// of the instruction they happened at: the index in the decoded stream or executed program.
+/// Instructions outside of the program, like a far routine, are given by their physical address.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// The stream ends before the instruction does.
+    TruncatedInstruction { offset: usize },
+    /// The byte doesn't start any instruction the decoder knows.
+    UnknownOpcode { offset: usize, byte: u8 },
+    /// The operands can't be used with the opcode, e.g. an immediate destination.
+    InvalidOperand { offset: usize, opcode: Opcode },
+    /// The instruction decodes, but the simulator can't execute it.
+    UnsupportedInstruction { offset: usize, opcode: Opcode },
+    /// The CPU is halted, so it doesn't execute the instruction.
+    Halted { offset: usize },
+}
+
+impl Error {
+    pub fn offset(&self) -> usize {
+        match self {
+            Error::TruncatedInstruction { offset }
+            | Error::UnknownOpcode { offset, .. }
+            | Error::InvalidOperand { offset, .. }
+            | Error::UnsupportedInstruction { offset, .. }
+            | Error::Halted { offset } => *offset,
+        }
+    }
+
+    /// The same error, at another offset.
+    pub(crate) fn with_offset(self, offset: usize) -> Error {
+        match self {
+            Error::TruncatedInstruction { .. } => Error::TruncatedInstruction { offset },
+            Error::UnknownOpcode { byte, .. } => Error::UnknownOpcode { offset, byte },
+            Error::InvalidOperand { opcode, .. } => Error::InvalidOperand { offset, opcode },
+            Error::UnsupportedInstruction { opcode, .. } => {
+                Error::UnsupportedInstruction { offset, opcode }
+            }
+            Error::Halted { .. } => Error::Halted { offset },
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
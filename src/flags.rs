@@ -0,0 +1,224 @@
+use crate::instruction::Width;
+
+/// The 8086 FLAGS register. Bits that have no meaning on the 8086 are kept as they are set.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Flags {
+    pub bits: u16,
+}
+
+impl Flags {
+    // Status flags
+    pub const CARRY: u16 = 1 << 0;
+    pub const PARITY: u16 = 1 << 2;
+    pub const AUXILIARY_CARRY: u16 = 1 << 4;
+    pub const ZERO: u16 = 1 << 6;
+    pub const SIGN: u16 = 1 << 7;
+    pub const OVERFLOW: u16 = 1 << 11;
+    // Control flags
+    pub const TRAP: u16 = 1 << 8;
+    pub const INTERRUPT: u16 = 1 << 9;
+    pub const DIRECTION: u16 = 1 << 10;
+
+    pub fn get(&self, flag: u16) -> bool {
+        self.bits & flag != 0
+    }
+
+    pub fn set(&mut self, flag: u16, value: bool) {
+        if value {
+            self.bits |= flag;
+        } else {
+            self.bits &= !flag;
+        }
+    }
+
+    /// Sets ZF, SF and PF from the result of an operation.
+    pub fn set_result_flags(&mut self, result: u16, width: Width) {
+        let (mask, sign) = width_masks(width);
+        self.set(Flags::ZERO, result & mask == 0);
+        self.set(Flags::SIGN, result & sign != 0);
+        // PF only looks at the low byte, and is set when the number of ones is even.
+        self.set(Flags::PARITY, (result as u8).count_ones().is_multiple_of(2));
+    }
+
+    /// Sets all status flags for `destination + source`.
+    pub fn set_add_flags(&mut self, destination: u16, source: u16, width: Width) {
+        self.set_adc_flags(destination, source, false, width);
+    }
+
+    /// Sets all status flags for `destination + source + carry`, as adc does.
+    pub fn set_adc_flags(&mut self, destination: u16, source: u16, carry: bool, width: Width) {
+        let (mask, sign) = width_masks(width);
+        let destination = destination & mask;
+        let source = source & mask;
+        let wide_result = destination as u32 + source as u32 + carry as u32;
+        let result = wide_result as u16 & mask;
+
+        self.set(Flags::CARRY, wide_result > mask as u32);
+        self.set(Flags::AUXILIARY_CARRY, (destination ^ source ^ result) & 0x10 != 0);
+        // Both operands have the same sign, but the result has a different one.
+        self.set(
+            Flags::OVERFLOW,
+            (destination ^ result) & (source ^ result) & sign != 0,
+        );
+        self.set_result_flags(result, width);
+    }
+
+    /// Sets all status flags for and, or, xor and test. CF and OF are always cleared.
+    /// AF is undefined, the 8086 clears it.
+    pub fn set_logic_flags(&mut self, result: u16, width: Width) {
+        self.set(Flags::CARRY, false);
+        self.set(Flags::OVERFLOW, false);
+        self.set(Flags::AUXILIARY_CARRY, false);
+        self.set_result_flags(result, width);
+    }
+
+    /// Sets all status flags for `destination - source`. Also used by cmp.
+    pub fn set_sub_flags(&mut self, destination: u16, source: u16, width: Width) {
+        self.set_sbb_flags(destination, source, false, width);
+    }
+
+    /// Sets all status flags for `destination - source - carry`, as sbb does.
+    pub fn set_sbb_flags(&mut self, destination: u16, source: u16, carry: bool, width: Width) {
+        let (mask, sign) = width_masks(width);
+        let destination = destination & mask;
+        let source = source & mask;
+        let result = destination.wrapping_sub(source).wrapping_sub(carry as u16) & mask;
+
+        self.set(Flags::CARRY, source as u32 + carry as u32 > destination as u32);
+        self.set(Flags::AUXILIARY_CARRY, (destination ^ source ^ result) & 0x10 != 0);
+        // The operands have different signs, and the result doesn't have the sign of the destination.
+        self.set(
+            Flags::OVERFLOW,
+            (destination ^ source) & (destination ^ result) & sign != 0,
+        );
+        self.set_result_flags(result, width);
+    }
+}
+
+/// The mask of all bits and the sign bit of a value of the given width.
+pub(crate) fn width_masks(width: Width) -> (u16, u16) {
+    match width {
+        Width::Byte => (0xff, 0x80),
+        Width::Word => (0xffff, 0x8000),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATUS: u16 = Flags::CARRY
+        | Flags::PARITY
+        | Flags::AUXILIARY_CARRY
+        | Flags::ZERO
+        | Flags::SIGN
+        | Flags::OVERFLOW;
+
+    fn add(destination: u16, source: u16, width: Width) -> u16 {
+        let mut flags = Flags::default();
+        flags.set_add_flags(destination, source, width);
+        return flags.bits & STATUS;
+    }
+
+    fn sub(destination: u16, source: u16, width: Width) -> u16 {
+        let mut flags = Flags::default();
+        flags.set_sub_flags(destination, source, width);
+        return flags.bits & STATUS;
+    }
+
+    #[test]
+    fn byte_addition() {
+        // 0x7f + 1 = 0x80 turns positive into negative.
+        assert_eq!(
+            add(0x7f, 1, Width::Byte),
+            Flags::OVERFLOW | Flags::SIGN | Flags::AUXILIARY_CARRY
+        );
+        assert_eq!(
+            add(0xff, 1, Width::Byte),
+            Flags::CARRY | Flags::ZERO | Flags::PARITY | Flags::AUXILIARY_CARRY
+        );
+        // Only the low nibble carries.
+        assert_eq!(add(0x0f, 1, Width::Byte), Flags::AUXILIARY_CARRY);
+        assert_eq!(add(0x10, 0x10, Width::Byte), 0);
+        // The high byte of a word operand doesn't matter.
+        assert_eq!(add(0x12ff, 1, Width::Byte), add(0xff, 1, Width::Byte));
+    }
+
+    #[test]
+    fn word_addition() {
+        assert_eq!(
+            add(0xffff, 1, Width::Word),
+            Flags::CARRY | Flags::ZERO | Flags::PARITY | Flags::AUXILIARY_CARRY
+        );
+        assert_eq!(
+            add(0x7fff, 1, Width::Word),
+            Flags::OVERFLOW | Flags::SIGN | Flags::PARITY | Flags::AUXILIARY_CARRY
+        );
+        // 0xff + 1 carries out of the low byte, but not out of the word. PF only sees the 0 byte.
+        assert_eq!(
+            add(0x00ff, 1, Width::Word),
+            Flags::PARITY | Flags::AUXILIARY_CARRY
+        );
+        assert_eq!(
+            add(0x8000, 0x8000, Width::Word),
+            Flags::CARRY | Flags::OVERFLOW | Flags::ZERO | Flags::PARITY
+        );
+    }
+
+    #[test]
+    fn subtraction() {
+        // 0x80 - 1 = 0x7f turns negative into positive.
+        assert_eq!(
+            sub(0x80, 1, Width::Byte),
+            Flags::OVERFLOW | Flags::AUXILIARY_CARRY
+        );
+        assert_eq!(
+            sub(0x8000, 1, Width::Word),
+            Flags::OVERFLOW | Flags::PARITY | Flags::AUXILIARY_CARRY
+        );
+        // 0 - 1 borrows.
+        assert_eq!(
+            sub(0, 1, Width::Byte),
+            Flags::CARRY | Flags::SIGN | Flags::PARITY | Flags::AUXILIARY_CARRY
+        );
+        assert_eq!(
+            sub(0, 1, Width::Word),
+            Flags::CARRY | Flags::SIGN | Flags::PARITY | Flags::AUXILIARY_CARRY
+        );
+        // Only the low nibble borrows.
+        assert_eq!(
+            sub(0x10, 1, Width::Byte),
+            Flags::PARITY | Flags::AUXILIARY_CARRY
+        );
+        assert_eq!(sub(5, 5, Width::Word), Flags::ZERO | Flags::PARITY);
+    }
+
+    #[test]
+    fn carry_in() {
+        let mut flags = Flags::default();
+        flags.set_adc_flags(0xff, 0, true, Width::Byte);
+        assert_eq!(
+            flags.bits & STATUS,
+            Flags::CARRY | Flags::ZERO | Flags::PARITY | Flags::AUXILIARY_CARRY
+        );
+
+        flags.set_sbb_flags(0, 0, true, Width::Word);
+        assert_eq!(
+            flags.bits & STATUS,
+            Flags::CARRY | Flags::SIGN | Flags::PARITY | Flags::AUXILIARY_CARRY
+        );
+
+        // 0x7f - 0xff - 1 borrows all the way around to 0x7f, which isn't an overflow.
+        flags.set_sbb_flags(0x7f, 0xff, true, Width::Byte);
+        assert_eq!(flags.bits & STATUS, Flags::CARRY | Flags::AUXILIARY_CARRY);
+    }
+
+    #[test]
+    fn control_flags_are_kept() {
+        let mut flags = Flags::default();
+        flags.set(Flags::DIRECTION, true);
+        flags.set_logic_flags(0, Width::Word);
+        assert!(flags.get(Flags::DIRECTION));
+        assert_eq!(flags.bits & STATUS, Flags::ZERO | Flags::PARITY);
+    }
+}
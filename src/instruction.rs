@@ -0,0 +1,416 @@
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Opcode {
+    Mov,
+    Xchg,
+    Lea,
+    Lds,
+    Les,
+    Xlat,
+    Lahf,
+    Sahf,
+    Cbw,
+    Cwd,
+    In,
+    Out,
+    Add,
+    Adc,
+    Sub,
+    Sbb,
+    Cmp,
+    Inc,
+    Dec,
+    Daa,
+    Das,
+    Aaa,
+    Aas,
+    Aam,
+    Aad,
+    And,
+    Or,
+    Xor,
+    Test,
+    Not,
+    Neg,
+    Mul,
+    Imul,
+    Div,
+    Idiv,
+    Shl,
+    Shr,
+    Sar,
+    Rol,
+    Ror,
+    Rcl,
+    Rcr,
+    Push,
+    Pop,
+    Pushf,
+    Popf,
+    Call,
+    CallFar,
+    Ret,
+    Retf,
+    Jmp,
+    JmpFar,
+    Int,
+    Int3,
+    Into,
+    Iret,
+    Clc,
+    Stc,
+    Cmc,
+    Cld,
+    Std,
+    Cli,
+    Sti,
+    Hlt,
+    Nop,
+    Wait,
+    Movs,
+    Cmps,
+    Scas,
+    Lods,
+    Stos,
+    Je,
+    Jl,
+    Jle,
+    Jb,
+    Jbe,
+    Jp,
+    Jo,
+    Js,
+    Jne,
+    Jnl,
+    Jnle,
+    Jnb,
+    Jnbe,
+    Jnp,
+    Jno,
+    Jns,
+    Loop,
+    Loopz,
+    Loopnz,
+    Jcxz,
+}
+impl Opcode {
+    pub fn is_shift(&self) -> bool {
+        matches!(
+            self,
+            Opcode::Shl
+                | Opcode::Shr
+                | Opcode::Sar
+                | Opcode::Rol
+                | Opcode::Ror
+                | Opcode::Rcl
+                | Opcode::Rcr
+        )
+    }
+
+    /// Far jumps and calls through memory read a segment along with the offset.
+    pub fn is_far(&self) -> bool {
+        matches!(self, Opcode::CallFar | Opcode::JmpFar)
+    }
+
+    /// The ModRM operand has to be in memory. Far jumps and calls read their target from there,
+    /// lea, lds and les take its address.
+    pub fn needs_memory_operand(&self) -> bool {
+        self.is_far() || matches!(self, Opcode::Lea | Opcode::Lds | Opcode::Les)
+    }
+
+    /// String instructions work on DS:SI and ES:DI and can carry a repeat prefix.
+    pub fn is_string(&self) -> bool {
+        matches!(
+            self,
+            Opcode::Movs | Opcode::Cmps | Opcode::Scas | Opcode::Lods | Opcode::Stos
+        )
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Opcode::Mov => "mov",
+            Opcode::Xchg => "xchg",
+            Opcode::Lea => "lea",
+            Opcode::Lds => "lds",
+            Opcode::Les => "les",
+            Opcode::Xlat => "xlat",
+            Opcode::Lahf => "lahf",
+            Opcode::Sahf => "sahf",
+            Opcode::Cbw => "cbw",
+            Opcode::Cwd => "cwd",
+            Opcode::In => "in",
+            Opcode::Out => "out",
+            Opcode::Add => "add",
+            Opcode::Adc => "adc",
+            Opcode::Sub => "sub",
+            Opcode::Sbb => "sbb",
+            Opcode::Inc => "inc",
+            Opcode::Dec => "dec",
+            Opcode::Daa => "daa",
+            Opcode::Das => "das",
+            Opcode::Aaa => "aaa",
+            Opcode::Aas => "aas",
+            Opcode::Aam => "aam",
+            Opcode::Aad => "aad",
+            Opcode::Cmp => "cmp",
+            Opcode::And => "and",
+            Opcode::Or => "or",
+            Opcode::Xor => "xor",
+            Opcode::Test => "test",
+            Opcode::Not => "not",
+            Opcode::Neg => "neg",
+            Opcode::Mul => "mul",
+            Opcode::Imul => "imul",
+            Opcode::Div => "div",
+            Opcode::Idiv => "idiv",
+            Opcode::Shl => "shl",
+            Opcode::Shr => "shr",
+            Opcode::Sar => "sar",
+            Opcode::Rol => "rol",
+            Opcode::Ror => "ror",
+            Opcode::Rcl => "rcl",
+            Opcode::Rcr => "rcr",
+            Opcode::Push => "push",
+            Opcode::Pop => "pop",
+            Opcode::Pushf => "pushf",
+            Opcode::Popf => "popf",
+            Opcode::Call | Opcode::CallFar => "call",
+            Opcode::Ret => "ret",
+            Opcode::Retf => "retf",
+            Opcode::Jmp | Opcode::JmpFar => "jmp",
+            Opcode::Int => "int",
+            Opcode::Int3 => "int3",
+            Opcode::Into => "into",
+            Opcode::Iret => "iret",
+            Opcode::Clc => "clc",
+            Opcode::Stc => "stc",
+            Opcode::Cmc => "cmc",
+            Opcode::Cld => "cld",
+            Opcode::Std => "std",
+            Opcode::Cli => "cli",
+            Opcode::Sti => "sti",
+            Opcode::Hlt => "hlt",
+            Opcode::Nop => "nop",
+            Opcode::Wait => "wait",
+            Opcode::Movs => "movs",
+            Opcode::Cmps => "cmps",
+            Opcode::Scas => "scas",
+            Opcode::Lods => "lods",
+            Opcode::Stos => "stos",
+            Opcode::Je => "je",
+            Opcode::Jl => "jl",
+            Opcode::Jle => "jle",
+            Opcode::Jb => "jb",
+            Opcode::Jbe => "jbe",
+            Opcode::Jp => "jp",
+            Opcode::Jo => "jo",
+            Opcode::Js => "js",
+            Opcode::Jne => "jne",
+            Opcode::Jnl => "jnl",
+            Opcode::Jnle => "jnle",
+            Opcode::Jnb => "jnb",
+            Opcode::Jnbe => "jnbe",
+            Opcode::Jnp => "jnp",
+            Opcode::Jno => "jno",
+            Opcode::Jns => "jns",
+            Opcode::Loop => "loop",
+            Opcode::Loopz => "loopz",
+            Opcode::Loopnz => "loopnz",
+            Opcode::Jcxz => "jcxz",
+        }
+    }
+}
+
+/// The repeat prefixes. 0xf3 is rep, which cmps and scas read as repe.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Repeat {
+    Rep,
+    Repne,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Width {
+    Byte,
+    Word,
+}
+
+/// The 16 bit general purpose registers, in the order of their 3 bit encoding.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum WordRegister {
+    Ax,
+    Cx,
+    Dx,
+    Bx,
+    Sp,
+    Bp,
+    Si,
+    Di,
+}
+impl WordRegister {
+    pub fn parse(val: u8) -> WordRegister {
+        match val & 0b111 {
+            0 => WordRegister::Ax,
+            1 => WordRegister::Cx,
+            2 => WordRegister::Dx,
+            3 => WordRegister::Bx,
+            4 => WordRegister::Sp,
+            5 => WordRegister::Bp,
+            6 => WordRegister::Si,
+            _ => WordRegister::Di,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WordRegister::Ax => "ax",
+            WordRegister::Cx => "cx",
+            WordRegister::Dx => "dx",
+            WordRegister::Bx => "bx",
+            WordRegister::Sp => "sp",
+            WordRegister::Bp => "bp",
+            WordRegister::Si => "si",
+            WordRegister::Di => "di",
+        }
+    }
+}
+
+/// The 8 bit halves of ax, cx, dx and bx, in the order of their 3 bit encoding.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ByteRegister {
+    Al,
+    Cl,
+    Dl,
+    Bl,
+    Ah,
+    Ch,
+    Dh,
+    Bh,
+}
+impl ByteRegister {
+    pub fn parse(val: u8) -> ByteRegister {
+        match val & 0b111 {
+            0 => ByteRegister::Al,
+            1 => ByteRegister::Cl,
+            2 => ByteRegister::Dl,
+            3 => ByteRegister::Bl,
+            4 => ByteRegister::Ah,
+            5 => ByteRegister::Ch,
+            6 => ByteRegister::Dh,
+            _ => ByteRegister::Bh,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ByteRegister::Al => "al",
+            ByteRegister::Cl => "cl",
+            ByteRegister::Dl => "dl",
+            ByteRegister::Bl => "bl",
+            ByteRegister::Ah => "ah",
+            ByteRegister::Ch => "ch",
+            ByteRegister::Dh => "dh",
+            ByteRegister::Bh => "bh",
+        }
+    }
+
+    pub fn parent(&self) -> WordRegister {
+        match self {
+            ByteRegister::Al | ByteRegister::Ah => WordRegister::Ax,
+            ByteRegister::Cl | ByteRegister::Ch => WordRegister::Cx,
+            ByteRegister::Dl | ByteRegister::Dh => WordRegister::Dx,
+            ByteRegister::Bl | ByteRegister::Bh => WordRegister::Bx,
+        }
+    }
+
+    pub fn is_high(&self) -> bool {
+        matches!(
+            self,
+            ByteRegister::Ah | ByteRegister::Ch | ByteRegister::Dh | ByteRegister::Bh
+        )
+    }
+}
+
+/// The segment registers, in the order of their 2 bit encoding.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum SegmentRegister {
+    Es,
+    Cs,
+    Ss,
+    Ds,
+}
+impl SegmentRegister {
+    pub fn parse(val: u8) -> SegmentRegister {
+        match val & 0b11 {
+            0 => SegmentRegister::Es,
+            1 => SegmentRegister::Cs,
+            2 => SegmentRegister::Ss,
+            _ => SegmentRegister::Ds,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SegmentRegister::Es => "es",
+            SegmentRegister::Cs => "cs",
+            SegmentRegister::Ss => "ss",
+            SegmentRegister::Ds => "ds",
+        }
+    }
+}
+
+/// A memory operand. Direct addresses have neither a base nor an index register,
+/// and carry the address in the displacement.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct EffectiveAddress {
+    pub base: Option<WordRegister>,
+    pub index: Option<WordRegister>,
+    pub displacement: i16,
+    // Set by a segment override prefix, replaces the default segment.
+    pub segment: Option<SegmentRegister>,
+}
+impl EffectiveAddress {
+    /// Builds the address from the 3 bit rm field of a memory mode ModRM byte.
+    pub fn parse(rm: u8, displacement: i16) -> EffectiveAddress {
+        let (base, index) = match rm & 0b111 {
+            0 => (Some(WordRegister::Bx), Some(WordRegister::Si)),
+            1 => (Some(WordRegister::Bx), Some(WordRegister::Di)),
+            2 => (Some(WordRegister::Bp), Some(WordRegister::Si)),
+            3 => (Some(WordRegister::Bp), Some(WordRegister::Di)),
+            4 => (None, Some(WordRegister::Si)),
+            5 => (None, Some(WordRegister::Di)),
+            6 => (Some(WordRegister::Bp), None),
+            _ => (Some(WordRegister::Bx), None),
+        };
+        return EffectiveAddress {
+            base,
+            index,
+            displacement,
+            segment: None,
+        };
+    }
+
+    pub fn direct(address: u16) -> EffectiveAddress {
+        return EffectiveAddress {
+            base: None,
+            index: None,
+            displacement: address as i16,
+            segment: None,
+        };
+    }
+
+    pub fn is_direct(&self) -> bool {
+        self.base.is_none() && self.index.is_none()
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Operand {
+    Register(WordRegister),
+    SubRegister(ByteRegister),
+    Segment(SegmentRegister),
+    Immediate(u16),
+    Memory(EffectiveAddress),
+    // Displacement relative to the end of the instruction.
+    Relative(i16),
+    // A direct far address.
+    Far { segment: u16, offset: u16 },
+}
@@ -1,8 +1,12 @@
+use crate::instruction::Opcode;
+
 #[derive(Eq, PartialEq, Hash, Debug)]
 pub enum Flag {
     S,
     W,
     D,
+    // Shift and rotate count: 1 if unset, cl if set.
+    V,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -10,6 +14,8 @@ pub enum WordField {
     Addr,
     Disp,
     Data,
+    // A displacement relative to the end of the instruction.
+    Rel,
 }
 
 
@@ -29,10 +35,20 @@ pub enum Argument {
     Byte,
     SegReg,
     FixedBit(u8),
+    // The accumulator, implied by the opcode. Doesn't take any bits.
+    Accumulator,
+    // The shift count, 1 or cl depending on the V flag. Doesn't take any bits.
+    Count,
+    // A direct far address: the offset word, followed by the segment word.
+    Pointer,
+    // An immediate byte that isn't sign extended, like the interrupt type or the base of aam.
+    ByteData,
+    // The dx port register of in and out. Doesn't take any bits.
+    Dx,
 }
 
-pub struct Instruction<'a> {
-    pub operand: &'a str,
+pub struct Instruction {
+    pub opcode: Opcode,
     pub offset: usize,
     pub max_byte_count: usize,
     pub flags: Vec<Flag>,
@@ -45,16 +61,16 @@ pub struct SecondaryOperand {
     pub value: u8,
 }
 
-pub enum InstructionLookup<'a> {
-    Instr(Instruction<'a>),
-    MultiInstr(Vec<(SecondaryOperand, Instruction<'a>)>),
+pub enum InstructionLookup {
+    Instr(Instruction),
+    MultiInstr(Vec<(SecondaryOperand, Instruction)>),
 }
-pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
+pub fn generate_instruction_table() -> Vec<(u8, InstructionLookup)> {
     return vec![
         (
             0b10001000,
             InstructionLookup::Instr(Instruction {
-                operand: "mov",
+                opcode: Opcode::Mov,
                 offset: 6,
                 max_byte_count: 4,
                 flags: vec![Flag::D, Flag::W],
@@ -69,7 +85,7 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b11000110,
             InstructionLookup::Instr(Instruction {
-                operand: "mov",
+                opcode: Opcode::Mov,
                 offset: 7,
                 max_byte_count: 6,
                 flags: vec![Flag::W],
@@ -85,7 +101,7 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b10110000,
             InstructionLookup::Instr(Instruction {
-                operand: "mov",
+                opcode: Opcode::Mov,
                 offset: 4,
                 max_byte_count: 3,
                 flags: vec![Flag::W],
@@ -98,47 +114,164 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b10100000,
             InstructionLookup::Instr(Instruction {
-                operand: "mov",
+                opcode: Opcode::Mov,
                 offset: 7,
                 max_byte_count: 3,
                 flags: vec![Flag::W],
-                extra_args: vec![Argument::Word(WordField::Addr)],
+                extra_args: vec![Argument::Accumulator, Argument::Word(WordField::Addr)],
             }),
         ),
         (
             0b10100010,
             InstructionLookup::Instr(Instruction {
-                operand: "mov",
+                opcode: Opcode::Mov,
                 offset: 7,
                 max_byte_count: 3,
                 flags: vec![Flag::W],
-                extra_args: vec![Argument::Word(WordField::Addr)],
+                extra_args: vec![Argument::Word(WordField::Addr), Argument::Accumulator],
+            }),
+        ),
+        (
+            0b10000110,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Xchg,
+                offset: 7,
+                max_byte_count: 4,
+                flags: vec![Flag::W],
+                extra_args: vec![
+                    Argument::Mode,
+                    Argument::Reg(Reg::Implicit),
+                    Argument::Rm,
+                    Argument::Word(WordField::Disp),
+                ],
+            }),
+        ),
+        (
+            // xchg ax, ax, has to come before the other xchg forms.
+            0b10010000,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Nop,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b10010000,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Xchg,
+                offset: 5,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![Argument::Accumulator, Argument::Reg(Reg::Implicit)],
             }),
         ),
         (
-            0b10001110,
+            0b10001101,
             InstructionLookup::Instr(Instruction {
-                operand: "mov",
+                opcode: Opcode::Lea,
                 offset: 8,
                 max_byte_count: 4,
                 flags: vec![],
                 extra_args: vec![
                     Argument::Mode,
-                    Argument::FixedBit(0),
-                    Argument::SegReg,
+                    Argument::Reg(Reg::Implicit),
                     Argument::Rm,
                     Argument::Word(WordField::Disp),
                 ],
             }),
         ),
         (
-            0b10001100,
+            0b11000101,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Lds,
+                offset: 8,
+                max_byte_count: 4,
+                flags: vec![],
+                extra_args: vec![
+                    Argument::Mode,
+                    Argument::Reg(Reg::Implicit),
+                    Argument::Rm,
+                    Argument::Word(WordField::Disp),
+                ],
+            }),
+        ),
+        (
+            0b11000100,
             InstructionLookup::Instr(Instruction {
-                operand: "mov",
+                opcode: Opcode::Les,
                 offset: 8,
                 max_byte_count: 4,
                 flags: vec![],
                 extra_args: vec![
+                    Argument::Mode,
+                    Argument::Reg(Reg::Implicit),
+                    Argument::Rm,
+                    Argument::Word(WordField::Disp),
+                ],
+            }),
+        ),
+        (
+            0b11010111,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Xlat,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b10011111,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Lahf,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b10011110,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Sahf,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b10011000,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Cbw,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b10011001,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Cwd,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            // Both directions between a segment register and r/m: 0x8c and 0x8e.
+            0b10001100,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Mov,
+                offset: 6,
+                max_byte_count: 4,
+                flags: vec![Flag::D],
+                extra_args: vec![
+                    Argument::FixedBit(0),
                     Argument::Mode,
                     Argument::FixedBit(0),
                     Argument::SegReg,
@@ -150,7 +283,7 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b00000000,
             InstructionLookup::Instr(Instruction {
-                operand: "add",
+                opcode: Opcode::Add,
                 offset: 6,
                 max_byte_count: 4,
                 flags: vec![Flag::D, Flag::W],
@@ -171,7 +304,7 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
                         value: 0b000,
                     },
                     Instruction {
-                        operand: "add",
+                        opcode: Opcode::Add,
                         offset: 6,
                         max_byte_count: 6,
                         flags: vec![Flag::S, Flag::W],
@@ -184,13 +317,89 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
                         ],
                     },
                 ),
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b001,
+                    },
+                    Instruction {
+                        opcode: Opcode::Or,
+                        offset: 6,
+                        max_byte_count: 6,
+                        flags: vec![Flag::S, Flag::W],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b001)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                            Argument::Word(WordField::Data),
+                        ],
+                    },
+                ),
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b010,
+                    },
+                    Instruction {
+                        opcode: Opcode::Adc,
+                        offset: 6,
+                        max_byte_count: 6,
+                        flags: vec![Flag::S, Flag::W],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b010)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                            Argument::Word(WordField::Data),
+                        ],
+                    },
+                ),
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b011,
+                    },
+                    Instruction {
+                        opcode: Opcode::Sbb,
+                        offset: 6,
+                        max_byte_count: 6,
+                        flags: vec![Flag::S, Flag::W],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b011)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                            Argument::Word(WordField::Data),
+                        ],
+                    },
+                ),
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b100,
+                    },
+                    Instruction {
+                        opcode: Opcode::And,
+                        offset: 6,
+                        max_byte_count: 6,
+                        flags: vec![Flag::S, Flag::W],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b100)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                            Argument::Word(WordField::Data),
+                        ],
+                    },
+                ),
                 (
                     SecondaryOperand {
                         offset: 11,
                         value: 0b101,
                     },
                     Instruction {
-                        operand: "sub",
+                        opcode: Opcode::Sub,
                         offset: 6,
                         max_byte_count: 6,
                         flags: vec![Flag::S, Flag::W],
@@ -203,13 +412,32 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
                         ],
                     },
                 ),
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b110,
+                    },
+                    Instruction {
+                        opcode: Opcode::Xor,
+                        offset: 6,
+                        max_byte_count: 6,
+                        flags: vec![Flag::S, Flag::W],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b110)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                            Argument::Word(WordField::Data),
+                        ],
+                    },
+                ),
                 (
                     SecondaryOperand {
                         offset: 11,
                         value: 0b111,
                     },
                     Instruction {
-                        operand: "cmp",
+                        opcode: Opcode::Cmp,
                         offset: 6,
                         max_byte_count: 6,
                         flags: vec![Flag::S, Flag::W],
@@ -227,17 +455,129 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b00000100,
             InstructionLookup::Instr(Instruction {
-                operand: "add",
+                opcode: Opcode::Add,
                 offset: 7,
                 max_byte_count: 3,
                 flags: vec![Flag::W],
-                extra_args: vec![Argument::Word(WordField::Data)],
+                extra_args: vec![Argument::Accumulator, Argument::Word(WordField::Data)],
+            }),
+        ),
+        (
+            0b00010000,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Adc,
+                offset: 6,
+                max_byte_count: 4,
+                flags: vec![Flag::D, Flag::W],
+                extra_args: vec![
+                    Argument::Mode,
+                    Argument::Reg(Reg::Implicit),
+                    Argument::Rm,
+                    Argument::Word(WordField::Disp),
+                ],
+            }),
+        ),
+        (
+            0b00010100,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Adc,
+                offset: 7,
+                max_byte_count: 3,
+                flags: vec![Flag::W],
+                extra_args: vec![Argument::Accumulator, Argument::Word(WordField::Data)],
+            }),
+        ),
+        (
+            0b00011000,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Sbb,
+                offset: 6,
+                max_byte_count: 4,
+                flags: vec![Flag::D, Flag::W],
+                extra_args: vec![
+                    Argument::Mode,
+                    Argument::Reg(Reg::Implicit),
+                    Argument::Rm,
+                    Argument::Word(WordField::Disp),
+                ],
+            }),
+        ),
+        (
+            0b00011100,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Sbb,
+                offset: 7,
+                max_byte_count: 3,
+                flags: vec![Flag::W],
+                extra_args: vec![Argument::Accumulator, Argument::Word(WordField::Data)],
+            }),
+        ),
+        (
+            0b01000000,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Inc,
+                offset: 5,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![Argument::Reg(Reg::Implicit)],
+            }),
+        ),
+        (
+            0b01001000,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Dec,
+                offset: 5,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![Argument::Reg(Reg::Implicit)],
             }),
         ),
+        (
+            // 0xfe and 0xff
+            0b11111110,
+            InstructionLookup::MultiInstr(vec![
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b000,
+                    },
+                    Instruction {
+                        opcode: Opcode::Inc,
+                        offset: 7,
+                        max_byte_count: 4,
+                        flags: vec![Flag::W],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b000)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                        ],
+                    },
+                ),
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b001,
+                    },
+                    Instruction {
+                        opcode: Opcode::Dec,
+                        offset: 7,
+                        max_byte_count: 4,
+                        flags: vec![Flag::W],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b001)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                        ],
+                    },
+                ),
+            ]),
+        ),
         (
             0b00101000,
             InstructionLookup::Instr(Instruction {
-                operand: "sub",
+                opcode: Opcode::Sub,
                 offset: 6,
                 max_byte_count: 4,
                 flags: vec![Flag::D, Flag::W],
@@ -252,17 +592,17 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b00101100,
             InstructionLookup::Instr(Instruction {
-                operand: "sub",
+                opcode: Opcode::Sub,
                 offset: 7,
                 max_byte_count: 3,
                 flags: vec![Flag::W],
-                extra_args: vec![Argument::Word(WordField::Data)],
+                extra_args: vec![Argument::Accumulator, Argument::Word(WordField::Data)],
             }),
         ),
         (
             0b00111000,
             InstructionLookup::Instr(Instruction {
-                operand: "cmp",
+                opcode: Opcode::Cmp,
                 offset: 6,
                 max_byte_count: 4,
                 flags: vec![Flag::D, Flag::W],
@@ -277,17 +617,941 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b00111100,
             InstructionLookup::Instr(Instruction {
-                operand: "cmp",
+                opcode: Opcode::Cmp,
                 offset: 7,
                 max_byte_count: 3,
                 flags: vec![Flag::W],
-                extra_args: vec![Argument::Word(WordField::Data)],
+                extra_args: vec![Argument::Accumulator, Argument::Word(WordField::Data)],
+            }),
+        ),
+        (
+            0b00001000,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Or,
+                offset: 6,
+                max_byte_count: 4,
+                flags: vec![Flag::D, Flag::W],
+                extra_args: vec![
+                    Argument::Mode,
+                    Argument::Reg(Reg::Implicit),
+                    Argument::Rm,
+                    Argument::Word(WordField::Disp),
+                ],
+            }),
+        ),
+        (
+            0b00001100,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Or,
+                offset: 7,
+                max_byte_count: 3,
+                flags: vec![Flag::W],
+                extra_args: vec![Argument::Accumulator, Argument::Word(WordField::Data)],
+            }),
+        ),
+        (
+            0b00100000,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::And,
+                offset: 6,
+                max_byte_count: 4,
+                flags: vec![Flag::D, Flag::W],
+                extra_args: vec![
+                    Argument::Mode,
+                    Argument::Reg(Reg::Implicit),
+                    Argument::Rm,
+                    Argument::Word(WordField::Disp),
+                ],
+            }),
+        ),
+        (
+            0b00100100,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::And,
+                offset: 7,
+                max_byte_count: 3,
+                flags: vec![Flag::W],
+                extra_args: vec![Argument::Accumulator, Argument::Word(WordField::Data)],
+            }),
+        ),
+        (
+            0b00110000,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Xor,
+                offset: 6,
+                max_byte_count: 4,
+                flags: vec![Flag::D, Flag::W],
+                extra_args: vec![
+                    Argument::Mode,
+                    Argument::Reg(Reg::Implicit),
+                    Argument::Rm,
+                    Argument::Word(WordField::Disp),
+                ],
+            }),
+        ),
+        (
+            0b00110100,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Xor,
+                offset: 7,
+                max_byte_count: 3,
+                flags: vec![Flag::W],
+                extra_args: vec![Argument::Accumulator, Argument::Word(WordField::Data)],
+            }),
+        ),
+        (
+            // test is commutative and has no D flag, the register always comes first.
+            0b10000100,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Test,
+                offset: 7,
+                max_byte_count: 4,
+                flags: vec![Flag::W],
+                extra_args: vec![
+                    Argument::Mode,
+                    Argument::Reg(Reg::Implicit),
+                    Argument::Rm,
+                    Argument::Word(WordField::Disp),
+                ],
+            }),
+        ),
+        (
+            0b10101000,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Test,
+                offset: 7,
+                max_byte_count: 3,
+                flags: vec![Flag::W],
+                extra_args: vec![Argument::Accumulator, Argument::Word(WordField::Data)],
+            }),
+        ),
+        (
+            0b11110110,
+            InstructionLookup::MultiInstr(vec![
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b000,
+                    },
+                    Instruction {
+                        opcode: Opcode::Test,
+                        offset: 7,
+                        max_byte_count: 6,
+                        flags: vec![Flag::W],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b000)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                            Argument::Word(WordField::Data),
+                        ],
+                    },
+                ),
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b010,
+                    },
+                    Instruction {
+                        opcode: Opcode::Not,
+                        offset: 7,
+                        max_byte_count: 4,
+                        flags: vec![Flag::W],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b010)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                        ],
+                    },
+                ),
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b011,
+                    },
+                    Instruction {
+                        opcode: Opcode::Neg,
+                        offset: 7,
+                        max_byte_count: 4,
+                        flags: vec![Flag::W],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b011)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                        ],
+                    },
+                ),
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b100,
+                    },
+                    Instruction {
+                        opcode: Opcode::Mul,
+                        offset: 7,
+                        max_byte_count: 4,
+                        flags: vec![Flag::W],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b100)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                        ],
+                    },
+                ),
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b101,
+                    },
+                    Instruction {
+                        opcode: Opcode::Imul,
+                        offset: 7,
+                        max_byte_count: 4,
+                        flags: vec![Flag::W],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b101)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                        ],
+                    },
+                ),
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b110,
+                    },
+                    Instruction {
+                        opcode: Opcode::Div,
+                        offset: 7,
+                        max_byte_count: 4,
+                        flags: vec![Flag::W],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b110)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                        ],
+                    },
+                ),
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b111,
+                    },
+                    Instruction {
+                        opcode: Opcode::Idiv,
+                        offset: 7,
+                        max_byte_count: 4,
+                        flags: vec![Flag::W],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b111)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                        ],
+                    },
+                ),
+            ]),
+        ),
+        (
+            // 0xd0 - 0xd3
+            0b11010000,
+            InstructionLookup::MultiInstr(vec![
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b000,
+                    },
+                    Instruction {
+                        opcode: Opcode::Rol,
+                        offset: 6,
+                        max_byte_count: 4,
+                        flags: vec![Flag::V, Flag::W],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b000)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                            Argument::Count,
+                        ],
+                    },
+                ),
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b001,
+                    },
+                    Instruction {
+                        opcode: Opcode::Ror,
+                        offset: 6,
+                        max_byte_count: 4,
+                        flags: vec![Flag::V, Flag::W],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b001)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                            Argument::Count,
+                        ],
+                    },
+                ),
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b010,
+                    },
+                    Instruction {
+                        opcode: Opcode::Rcl,
+                        offset: 6,
+                        max_byte_count: 4,
+                        flags: vec![Flag::V, Flag::W],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b010)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                            Argument::Count,
+                        ],
+                    },
+                ),
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b011,
+                    },
+                    Instruction {
+                        opcode: Opcode::Rcr,
+                        offset: 6,
+                        max_byte_count: 4,
+                        flags: vec![Flag::V, Flag::W],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b011)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                            Argument::Count,
+                        ],
+                    },
+                ),
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b100,
+                    },
+                    Instruction {
+                        opcode: Opcode::Shl,
+                        offset: 6,
+                        max_byte_count: 4,
+                        flags: vec![Flag::V, Flag::W],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b100)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                            Argument::Count,
+                        ],
+                    },
+                ),
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b101,
+                    },
+                    Instruction {
+                        opcode: Opcode::Shr,
+                        offset: 6,
+                        max_byte_count: 4,
+                        flags: vec![Flag::V, Flag::W],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b101)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                            Argument::Count,
+                        ],
+                    },
+                ),
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b111,
+                    },
+                    Instruction {
+                        opcode: Opcode::Sar,
+                        offset: 6,
+                        max_byte_count: 4,
+                        flags: vec![Flag::V, Flag::W],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b111)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                            Argument::Count,
+                        ],
+                    },
+                ),
+            ]),
+        ),
+        (
+            0b01010000,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Push,
+                offset: 5,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![Argument::Reg(Reg::Implicit)],
+            }),
+        ),
+        (
+            0b01011000,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Pop,
+                offset: 5,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![Argument::Reg(Reg::Implicit)],
+            }),
+        ),
+        (
+            // 0x06, 0x0e, 0x16 and 0x1e
+            0b00000000,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Push,
+                offset: 3,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![
+                    Argument::SegReg,
+                    Argument::FixedBit(1),
+                    Argument::FixedBit(1),
+                    Argument::FixedBit(0),
+                ],
+            }),
+        ),
+        (
+            // 0x07, 0x0f, 0x17 and 0x1f
+            0b00000000,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Pop,
+                offset: 3,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![
+                    Argument::SegReg,
+                    Argument::FixedBit(1),
+                    Argument::FixedBit(1),
+                    Argument::FixedBit(1),
+                ],
+            }),
+        ),
+        (
+            0b11111111,
+            InstructionLookup::MultiInstr(vec![
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b010,
+                    },
+                    Instruction {
+                        opcode: Opcode::Call,
+                        offset: 8,
+                        max_byte_count: 4,
+                        flags: vec![],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b010)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                        ],
+                    },
+                ),
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b011,
+                    },
+                    Instruction {
+                        opcode: Opcode::CallFar,
+                        offset: 8,
+                        max_byte_count: 4,
+                        flags: vec![],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b011)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                        ],
+                    },
+                ),
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b100,
+                    },
+                    Instruction {
+                        opcode: Opcode::Jmp,
+                        offset: 8,
+                        max_byte_count: 4,
+                        flags: vec![],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b100)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                        ],
+                    },
+                ),
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b101,
+                    },
+                    Instruction {
+                        opcode: Opcode::JmpFar,
+                        offset: 8,
+                        max_byte_count: 4,
+                        flags: vec![],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b101)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                        ],
+                    },
+                ),
+                (
+                    SecondaryOperand {
+                        offset: 11,
+                        value: 0b110,
+                    },
+                    Instruction {
+                        opcode: Opcode::Push,
+                        offset: 8,
+                        max_byte_count: 4,
+                        flags: vec![],
+                        extra_args: vec![
+                            Argument::Mode,
+                            Argument::Reg(Reg::Explicit(0b110)),
+                            Argument::Rm,
+                            Argument::Word(WordField::Disp),
+                        ],
+                    },
+                ),
+            ]),
+        ),
+        (
+            0b10001111,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Pop,
+                offset: 8,
+                max_byte_count: 4,
+                flags: vec![],
+                extra_args: vec![
+                    Argument::Mode,
+                    Argument::Reg(Reg::Explicit(0)),
+                    Argument::Rm,
+                    Argument::Word(WordField::Disp),
+                ],
+            }),
+        ),
+        (
+            0b10011100,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Pushf,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b10011101,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Popf,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b11101000,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Call,
+                offset: 8,
+                max_byte_count: 3,
+                flags: vec![],
+                extra_args: vec![Argument::Word(WordField::Rel)],
+            }),
+        ),
+        (
+            0b10011010,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::CallFar,
+                offset: 8,
+                max_byte_count: 5,
+                flags: vec![],
+                extra_args: vec![Argument::Pointer],
+            }),
+        ),
+        (
+            0b11000011,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Ret,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b11000010,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Ret,
+                offset: 8,
+                max_byte_count: 3,
+                flags: vec![],
+                extra_args: vec![Argument::Word(WordField::Data)],
+            }),
+        ),
+        (
+            0b11001011,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Retf,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b11001010,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Retf,
+                offset: 8,
+                max_byte_count: 3,
+                flags: vec![],
+                extra_args: vec![Argument::Word(WordField::Data)],
+            }),
+        ),
+        (
+            0b11100100,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::In,
+                offset: 7,
+                max_byte_count: 2,
+                flags: vec![Flag::W],
+                extra_args: vec![Argument::Accumulator, Argument::ByteData],
+            }),
+        ),
+        (
+            0b11101100,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::In,
+                offset: 7,
+                max_byte_count: 1,
+                flags: vec![Flag::W],
+                extra_args: vec![Argument::Accumulator, Argument::Dx],
+            }),
+        ),
+        (
+            0b11100110,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Out,
+                offset: 7,
+                max_byte_count: 2,
+                flags: vec![Flag::W],
+                extra_args: vec![Argument::ByteData, Argument::Accumulator],
+            }),
+        ),
+        (
+            0b11101110,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Out,
+                offset: 7,
+                max_byte_count: 1,
+                flags: vec![Flag::W],
+                extra_args: vec![Argument::Dx, Argument::Accumulator],
+            }),
+        ),
+        (
+            0b11111000,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Clc,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b11111001,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Stc,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b11110101,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Cmc,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b11111100,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Cld,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b11111101,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Std,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b11111010,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Cli,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b11111011,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Sti,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b11110100,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Hlt,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b10011011,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Wait,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b00100111,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Daa,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b00101111,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Das,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b00110111,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Aaa,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b00111111,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Aas,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b11010100,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Aam,
+                offset: 8,
+                max_byte_count: 2,
+                flags: vec![],
+                extra_args: vec![Argument::ByteData],
+            }),
+        ),
+        (
+            0b11010101,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Aad,
+                offset: 8,
+                max_byte_count: 2,
+                flags: vec![],
+                extra_args: vec![Argument::ByteData],
+            }),
+        ),
+        (
+            0b11001101,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Int,
+                offset: 8,
+                max_byte_count: 2,
+                flags: vec![],
+                extra_args: vec![Argument::ByteData],
+            }),
+        ),
+        (
+            0b11001100,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Int3,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b11001110,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Into,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b11001111,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Iret,
+                offset: 8,
+                max_byte_count: 1,
+                flags: vec![],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b11101011,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Jmp,
+                offset: 8,
+                max_byte_count: 2,
+                flags: vec![],
+                extra_args: vec![Argument::Byte],
+            }),
+        ),
+        (
+            0b11101001,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Jmp,
+                offset: 8,
+                max_byte_count: 3,
+                flags: vec![],
+                extra_args: vec![Argument::Word(WordField::Rel)],
+            }),
+        ),
+        (
+            0b11101010,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::JmpFar,
+                offset: 8,
+                max_byte_count: 5,
+                flags: vec![],
+                extra_args: vec![Argument::Pointer],
+            }),
+        ),
+        (
+            0b10100100,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Movs,
+                offset: 7,
+                max_byte_count: 1,
+                flags: vec![Flag::W],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b10100110,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Cmps,
+                offset: 7,
+                max_byte_count: 1,
+                flags: vec![Flag::W],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b10101110,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Scas,
+                offset: 7,
+                max_byte_count: 1,
+                flags: vec![Flag::W],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b10101100,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Lods,
+                offset: 7,
+                max_byte_count: 1,
+                flags: vec![Flag::W],
+                extra_args: vec![],
+            }),
+        ),
+        (
+            0b10101010,
+            InstructionLookup::Instr(Instruction {
+                opcode: Opcode::Stos,
+                offset: 7,
+                max_byte_count: 1,
+                flags: vec![Flag::W],
+                extra_args: vec![],
             }),
         ),
         (
             0b01110100,
             InstructionLookup::Instr(Instruction {
-                operand: "je",
+                opcode: Opcode::Je,
                 offset: 8,
                 max_byte_count: 2,
                 flags: vec![],
@@ -297,7 +1561,7 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b01111100,
             InstructionLookup::Instr(Instruction {
-                operand: "jl",
+                opcode: Opcode::Jl,
                 offset: 8,
                 max_byte_count: 2,
                 flags: vec![],
@@ -307,7 +1571,7 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b01111110,
             InstructionLookup::Instr(Instruction {
-                operand: "jle",
+                opcode: Opcode::Jle,
                 offset: 8,
                 max_byte_count: 2,
                 flags: vec![],
@@ -317,7 +1581,7 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b01110010,
             InstructionLookup::Instr(Instruction {
-                operand: "jb",
+                opcode: Opcode::Jb,
                 offset: 8,
                 max_byte_count: 2,
                 flags: vec![],
@@ -327,7 +1591,7 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b01110110,
             InstructionLookup::Instr(Instruction {
-                operand: "jbe",
+                opcode: Opcode::Jbe,
                 offset: 8,
                 max_byte_count: 2,
                 flags: vec![],
@@ -337,7 +1601,7 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b01111010,
             InstructionLookup::Instr(Instruction {
-                operand: "jp",
+                opcode: Opcode::Jp,
                 offset: 8,
                 max_byte_count: 2,
                 flags: vec![],
@@ -347,7 +1611,7 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b01110000,
             InstructionLookup::Instr(Instruction {
-                operand: "jo",
+                opcode: Opcode::Jo,
                 offset: 8,
                 max_byte_count: 2,
                 flags: vec![],
@@ -357,7 +1621,7 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b01111000,
             InstructionLookup::Instr(Instruction {
-                operand: "js",
+                opcode: Opcode::Js,
                 offset: 8,
                 max_byte_count: 2,
                 flags: vec![],
@@ -367,7 +1631,7 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b01110101,
             InstructionLookup::Instr(Instruction {
-                operand: "jne",
+                opcode: Opcode::Jne,
                 offset: 8,
                 max_byte_count: 2,
                 flags: vec![],
@@ -377,7 +1641,7 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b01111101,
             InstructionLookup::Instr(Instruction {
-                operand: "jnl",
+                opcode: Opcode::Jnl,
                 offset: 8,
                 max_byte_count: 2,
                 flags: vec![],
@@ -387,7 +1651,7 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b01111111,
             InstructionLookup::Instr(Instruction {
-                operand: "jnle",
+                opcode: Opcode::Jnle,
                 offset: 8,
                 max_byte_count: 2,
                 flags: vec![],
@@ -397,7 +1661,7 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b01110011,
             InstructionLookup::Instr(Instruction {
-                operand: "jnb",
+                opcode: Opcode::Jnb,
                 offset: 8,
                 max_byte_count: 2,
                 flags: vec![],
@@ -407,7 +1671,7 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b01110111,
             InstructionLookup::Instr(Instruction {
-                operand: "jnbe",
+                opcode: Opcode::Jnbe,
                 offset: 8,
                 max_byte_count: 2,
                 flags: vec![],
@@ -417,7 +1681,7 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b01111011,
             InstructionLookup::Instr(Instruction {
-                operand: "jnp",
+                opcode: Opcode::Jnp,
                 offset: 8,
                 max_byte_count: 2,
                 flags: vec![],
@@ -427,7 +1691,7 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b01110001,
             InstructionLookup::Instr(Instruction {
-                operand: "jno",
+                opcode: Opcode::Jno,
                 offset: 8,
                 max_byte_count: 2,
                 flags: vec![],
@@ -437,7 +1701,7 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b01111001,
             InstructionLookup::Instr(Instruction {
-                operand: "jns",
+                opcode: Opcode::Jns,
                 offset: 8,
                 max_byte_count: 2,
                 flags: vec![],
@@ -447,7 +1711,7 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b11100010,
             InstructionLookup::Instr(Instruction {
-                operand: "loop",
+                opcode: Opcode::Loop,
                 offset: 8,
                 max_byte_count: 2,
                 flags: vec![],
@@ -457,7 +1721,7 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b11100001,
             InstructionLookup::Instr(Instruction {
-                operand: "loopz",
+                opcode: Opcode::Loopz,
                 offset: 8,
                 max_byte_count: 2,
                 flags: vec![],
@@ -467,7 +1731,7 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b11100000,
             InstructionLookup::Instr(Instruction {
-                operand: "loopnz",
+                opcode: Opcode::Loopnz,
                 offset: 8,
                 max_byte_count: 2,
                 flags: vec![],
@@ -477,7 +1741,7 @@ pub fn generate_instruction_table<'a>() -> Vec<(u8, InstructionLookup<'a>)> {
         (
             0b11100011,
             InstructionLookup::Instr(Instruction {
-                operand: "jcxz",
+                opcode: Opcode::Jcxz,
                 offset: 8,
                 max_byte_count: 2,
                 flags: vec![],
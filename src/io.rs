@@ -0,0 +1,69 @@
+use std::ops::RangeInclusive;
+
+use crate::instruction::Width;
+
+/// A peripheral that `in` and `out` talk to. It gets the port that was accessed,
+/// so one device can answer a whole range of ports.
+pub trait Device {
+    fn read(&mut self, port: u16, width: Width) -> u16;
+    fn write(&mut self, port: u16, width: Width, value: u16);
+}
+
+/// A port access no device was attached to.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PortAccess {
+    Read { port: u16, width: Width },
+    Write { port: u16, width: Width, value: u16 },
+}
+
+/// The 64K I/O ports of the 8086. Ports without a device read as all ones, like a floating bus,
+/// and ignore writes. Both are logged instead.
+#[derive(Default)]
+pub struct IoBus {
+    devices: Vec<(RangeInclusive<u16>, Box<dyn Device>)>,
+    unhandled: Vec<PortAccess>,
+}
+
+impl IoBus {
+    pub fn new() -> IoBus {
+        return IoBus::default();
+    }
+
+    /// Routes the given ports to `device`. Devices attached later take precedence.
+    pub fn attach(&mut self, ports: RangeInclusive<u16>, device: Box<dyn Device>) {
+        self.devices.insert(0, (ports, device));
+    }
+
+    fn device(&mut self, port: u16) -> Option<&mut Box<dyn Device>> {
+        return self
+            .devices
+            .iter_mut()
+            .find(|(ports, _)| ports.contains(&port))
+            .map(|(_, device)| device);
+    }
+
+    pub fn read(&mut self, port: u16, width: Width) -> u16 {
+        if let Some(device) = self.device(port) {
+            return device.read(port, width);
+        }
+        self.unhandled.push(PortAccess::Read { port, width });
+        match width {
+            Width::Byte => 0xff,
+            Width::Word => 0xffff,
+        }
+    }
+
+    pub fn write(&mut self, port: u16, width: Width, value: u16) {
+        match self.device(port) {
+            Some(device) => device.write(port, width, value),
+            None => self
+                .unhandled
+                .push(PortAccess::Write { port, width, value }),
+        }
+    }
+
+    /// The accesses to ports without a device, in the order they happened.
+    pub fn unhandled(&self) -> &[PortAccess] {
+        &self.unhandled
+    }
+}
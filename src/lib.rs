@@ -0,0 +1,18 @@
+//! An 8086 decoder and simulator.
+//!
+//! [`decoder::decode_bitstream`] turns machine code into instructions, which print as assembly.
+//! [`simulator::Cpu`] executes them one [`step`](simulator::Cpu::step) at a time, and exposes
+//! its registers, flags and memory in between. `in` and `out` go to the devices attached
+//! to its [`io::IoBus`].
+#![allow(clippy::needless_return)]
+
+pub mod decoder;
+mod display;
+pub mod error;
+pub mod flags;
+pub mod instruction;
+mod instruction_table;
+pub mod io;
+pub mod memory;
+pub mod simulator;
+pub mod timing;
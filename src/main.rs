@@ -1,54 +1,233 @@
-mod decoder;
-mod instruction_table;
-mod simulator;
+#![allow(clippy::needless_return)]
 
 use std::env;
 use std::fs;
-use std::io::Result;
+use std::io::{Error, ErrorKind, Result};
+use std::process::ExitCode;
 
-use self::decoder::DecodedArgument;
+use sim_8086::decoder;
+use sim_8086::memory::Memory;
+use sim_8086::simulator;
+use sim_8086::timing::TimingModel;
 
-fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
+// A file that is copied into memory before execution starts.
+struct MemoryLoad {
+    file: String,
+    segment: u16,
+    offset: u16,
+}
 
-    let source_file = &args[1];
-    let mode = &args[2];
+// A file the memory is written to after execution. Without a range, the whole 1 MiB image is written.
+struct MemoryDump {
+    file: String,
+    range: Option<(u16, u16, u16)>,
+}
 
-    let input = fs::read(source_file)?;
+fn invalid_argument(message: String) -> Error {
+    return Error::new(ErrorKind::InvalidInput, message);
+}
 
-    if mode == "decode" {
-        let decoded = decoder::decode_bitstream(input)?;
+fn parse_hex(val: &str) -> Result<u16> {
+    u16::from_str_radix(val, 16)
+        .map_err(|_| invalid_argument(format!("Invalid hex number: {}", val)))
+}
 
-        for line in &decoded {
-            if line.source == "" {
-                println!("{} {}", line.operand, line.destination);
-            } else {
-                println!("{} {}, {}", line.operand, line.destination, line.source);
+/// Parses `segment:offset`, both in hex.
+fn parse_address(val: &str) -> Result<(u16, u16)> {
+    let (segment, offset) = val
+        .split_once(':')
+        .ok_or_else(|| invalid_argument(format!("Expected segment:offset, got {}", val)))?;
+    return Ok((parse_hex(segment)?, parse_hex(offset)?));
+}
+
+/// Parses `segment:start-end`, all in hex. The end offset is included.
+fn parse_range(val: &str) -> Result<(u16, u16, u16)> {
+    let (address, end) = val
+        .split_once('-')
+        .ok_or_else(|| invalid_argument(format!("Expected segment:start-end, got {}", val)))?;
+    let (segment, start) = parse_address(address)?;
+    return Ok((segment, start, parse_hex(end)?));
+}
+
+struct Options {
+    loads: Vec<MemoryLoad>,
+    dumps: Vec<MemoryDump>,
+    timing: TimingModel,
+    // Number of words to print from the top of the stack after the run.
+    stack_words: usize,
+}
+
+/// Reads `--load <file> [segment:offset]`, `--dump <file> [segment:start-end]`, `--stack <words>`
+/// and `--8088` options.
+fn parse_options(options: &[String]) -> Result<Options> {
+    let mut output = Options {
+        loads: Vec::new(),
+        dumps: Vec::new(),
+        timing: TimingModel::I8086,
+        stack_words: 0,
+    };
+
+    let mut idx = 0;
+    while idx < options.len() {
+        let option = &options[idx];
+        if option == "--8088" {
+            output.timing = TimingModel::I8088;
+            idx += 1;
+            continue;
+        }
+        if option == "--stack" {
+            let words = options
+                .get(idx + 1)
+                .ok_or_else(|| invalid_argument(format!("{} needs a word count", option)))?;
+            output.stack_words = words
+                .parse()
+                .map_err(|_| invalid_argument(format!("Invalid word count: {}", words)))?;
+            idx += 2;
+            continue;
+        }
+
+        let file = options
+            .get(idx + 1)
+            .ok_or_else(|| invalid_argument(format!("{} needs a file", option)))?
+            .to_string();
+        // The address is optional, so only take the next argument if it isn't another option.
+        let address = options
+            .get(idx + 2)
+            .filter(|address| !address.starts_with("--"));
+        idx += if address.is_some() { 3 } else { 2 };
+
+        match option.as_str() {
+            "--load" => {
+                let (segment, offset) = match address {
+                    Some(address) => parse_address(address)?,
+                    None => (0, 0),
+                };
+                output.loads.push(MemoryLoad {
+                    file,
+                    segment,
+                    offset,
+                });
+            }
+            "--dump" => {
+                let range = match address {
+                    Some(address) => Some(parse_range(address)?),
+                    None => None,
+                };
+                output.dumps.push(MemoryDump { file, range });
             }
+            _ => return Err(invalid_argument(format!("Unknown option {}", option))),
         }
-    } else if mode == "execute" {
-        let input: Vec<DecodedArgument> = fs::read_to_string(source_file)?
-            .split("\n")
-            .filter(|s| !s.is_empty())
-            .map(|s| DecodedArgument::parse(s).expect("The argument couldn't be decoded. Please verify that your formatting is correct."))
-            .collect();
+    }
+    return Ok(output);
+}
 
-        for line in &input {
-                println!("{} {}, {}", line.operand, line.destination, line.source);
+const USAGE: &str = "Usage: sim_8086 <file> decode | sim_8086 <file> execute [--8088] [--stack <words>] [--load <file> [seg:off]] [--dump <file> [seg:start-end]]";
+
+fn run(args: &[String]) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let (source_file, mode) = match args {
+        [_, source_file, mode, ..] => (source_file, mode),
+        _ => return Err(invalid_argument(USAGE.to_string()).into()),
+    };
+
+    let input = fs::read(source_file)?;
+
+    if mode == "decode" {
+        for line in decoder::disassemble(&input)? {
+            println!("{}", line);
+        }
+    } else if mode == "execute" {
+        let options = parse_options(&args[3..])?;
+        let mut memory = Memory::new();
+        for load in &options.loads {
+            let data = fs::read(&load.file)?;
+            memory.load(Memory::physical_address(load.segment, load.offset), &data);
         }
-        let result = simulator::execute_instructions(&input)?;
 
-        assert!(&input.len() == &result.steps.len(), "ERROR: Not all instructions could be decoded.");
+        let result = simulator::execute_instructions(&input, memory, options.timing)?;
 
-        for idx in 0..input.len() {
-            println!("{} {}, {}; {}: {:x} --> {:x}", input[idx].operand, input[idx].destination, input[idx].source, 
-                     result.steps[idx].dest_reg, result.steps[idx].dest_start, result.steps[idx].dest_end);
+        let mut elapsed_cycles: u64 = 0;
+        for step in &result.steps {
+            elapsed_cycles += step.cycles.total() as u64;
+            let cycles = format!(
+                "clocks: +{} = {} ({} + {}ea + {}p)",
+                step.cycles.total(),
+                elapsed_cycles,
+                step.cycles.base,
+                step.cycles.effective_address,
+                step.cycles.transfer_penalty
+            );
+            // Instructions inside a call are indented by its depth.
+            let indent = "  ".repeat(step.call_depth as usize);
+            let stack = if step.stack_wrapped {
+                ", stack wrapped around"
+            } else {
+                ""
+            };
+            match &step.dest_reg {
+                Some(dest_reg) => println!(
+                    "{}{}; {}, {}: {:x} --> {:x}, ip: {:x} --> {:x}{}",
+                    indent,
+                    step.instruction,
+                    cycles,
+                    dest_reg,
+                    step.dest_start,
+                    step.dest_end,
+                    step.ip_start,
+                    step.ip_end,
+                    stack
+                ),
+                None => println!(
+                    "{}{}; {}, ip: {:x} --> {:x}{}",
+                    indent, step.instruction, cycles, step.ip_start, step.ip_end, stack
+                ),
+            }
         }
         println!("\n Final registers:");
-        for (key, value) in result.final_status {
-            println!("{}: {:x}", key, simulator::get_register_value(&value));
+        for (key, value) in &result.final_status {
+            println!("{}: {:x}", key, simulator::get_register_value(value));
+        }
+        println!("ip: {:x}", result.final_ip);
+        println!("flags: {}", result.final_flags);
+        println!("clocks: {}", result.total_cycles);
+        println!("stopped by: {}", result.halt_reason);
+
+        if options.stack_words > 0 {
+            println!("\n Stack:");
+            let sp = simulator::get_register_value(&result.final_status["sp"]);
+            for (idx, value) in result.stack(options.stack_words).iter().enumerate() {
+                println!("{:x}: {:x}", sp.wrapping_add(2 * idx as u16), value);
+            }
+        }
+
+        // Nothing is attached to the ports, so every in and out ends up here.
+        if !result.unhandled_ports.is_empty() {
+            println!("\n Unhandled ports:");
+            for access in &result.unhandled_ports {
+                println!("{}", access);
+            }
         }
 
+        for dump in &options.dumps {
+            match dump.range {
+                Some((segment, start, end)) => {
+                    fs::write(&dump.file, result.final_memory.dump(segment, start, end))?
+                }
+                None => fs::write(&dump.file, result.final_memory.bytes())?,
+            }
+        }
+    } else {
+        return Err(invalid_argument(format!("Unknown mode {}\n{}", mode, USAGE)).into());
     }
     Ok(())
 }
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("ERROR: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
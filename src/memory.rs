@@ -0,0 +1,81 @@
+use crate::instruction::Width;
+
+/// The 8086 can address 1 MiB through its 20 bit address bus.
+pub const MEMORY_SIZE: usize = 1 << 20;
+
+pub struct Memory {
+    bytes: Vec<u8>,
+}
+
+impl Default for Memory {
+    fn default() -> Memory {
+        Memory::new()
+    }
+}
+
+impl Memory {
+    pub fn new() -> Memory {
+        return Memory {
+            bytes: vec![0; MEMORY_SIZE],
+        };
+    }
+
+    /// Translates segment:offset into a physical address. Addresses past 1 MiB wrap around to 0.
+    pub fn physical_address(segment: u16, offset: u16) -> usize {
+        return (((segment as usize) << 4) + offset as usize) % MEMORY_SIZE;
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn read_byte(&self, address: usize) -> u8 {
+        return self.bytes[address % MEMORY_SIZE];
+    }
+
+    pub fn write_byte(&mut self, address: usize, value: u8) {
+        self.bytes[address % MEMORY_SIZE] = value;
+    }
+
+    /// Reads a little-endian value at segment:offset. The high byte of a word
+    /// at offset 0xffff is read from the start of the same segment.
+    pub fn read(&self, segment: u16, offset: u16, width: Width) -> u16 {
+        let low = self.read_byte(Memory::physical_address(segment, offset)) as u16;
+        match width {
+            Width::Byte => low,
+            Width::Word => {
+                let high_address = Memory::physical_address(segment, offset.wrapping_add(1));
+                ((self.read_byte(high_address) as u16) << 8) | low
+            }
+        }
+    }
+
+    pub fn write(&mut self, segment: u16, offset: u16, width: Width, value: u16) {
+        self.write_byte(Memory::physical_address(segment, offset), value as u8);
+        if width == Width::Word {
+            let high_address = Memory::physical_address(segment, offset.wrapping_add(1));
+            self.write_byte(high_address, (value >> 8) as u8);
+        }
+    }
+
+    /// Copies `data` into memory, starting at the given physical address.
+    pub fn load(&mut self, address: usize, data: &[u8]) {
+        for (idx, byte) in data.iter().enumerate() {
+            self.write_byte(address + idx, *byte);
+        }
+    }
+
+    /// Copies segment:start up to and including segment:end out of memory.
+    pub fn dump(&self, segment: u16, start: u16, end: u16) -> Vec<u8> {
+        let mut output = Vec::new();
+        let mut offset = start;
+        loop {
+            output.push(self.read_byte(Memory::physical_address(segment, offset)));
+            if offset == end {
+                break;
+            }
+            offset = offset.wrapping_add(1);
+        }
+        return output;
+    }
+}
@@ -1,17 +1,73 @@
 use std::collections::HashMap;
 
-use crate::decoder::DecodedArgument;
-use std::io::Result;
+use crate::decoder::{decode_next, DecodedArgument};
+use crate::error::{Error, Result};
+use crate::flags::{width_masks, Flags};
+use crate::instruction::{
+    ByteRegister, EffectiveAddress, Opcode, Operand, Repeat, SegmentRegister, Width, WordRegister,
+};
+use crate::instruction_table::{generate_instruction_table, InstructionLookup};
+use crate::io::{IoBus, PortAccess};
+use crate::memory::Memory;
+use crate::timing::{instruction_cycles, Cycles, TimingModel};
 
 pub struct ExecutedOperation {
-    pub dest_reg: String,
+    pub instruction: DecodedArgument,
+    // Either a register or a memory operand. Jumps that don't write anything have no destination.
+    pub dest_reg: Option<String>,
     pub dest_start: u16,
     pub dest_end: u16,
+    pub ip_start: u16,
+    pub ip_end: u16,
+    pub cycles: Cycles,
+    // SP wrapped around the end of the stack segment.
+    pub stack_wrapped: bool,
+    // Number of calls that hadn't returned yet when the instruction started.
+    pub call_depth: u32,
 }
 
+/// Why a run stopped.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum HaltReason {
+    // Execution ran past the last instruction of the program.
+    EndOfProgram,
+    Hlt,
+    // The program ran for MAX_STEPS instructions without stopping.
+    StepLimit,
+    // An interrupt whose vector is still 0000:0000.
+    UnhandledInterrupt(u8),
+}
+
+/// The segment `execute_instructions` loads programs at. Physical 0 to 0x3ff holds the interrupt
+/// vector table.
+pub const PROGRAM_SEGMENT: u16 = 0x1000;
+
+/// Runs end after this many instructions, so a program that never halts still stops.
+pub const MAX_STEPS: usize = 100_000;
+
 pub struct SimulationResult {
     pub steps: Vec<ExecutedOperation>,
     pub final_status: HashMap<String, Register>,
+    pub final_ip: u16,
+    pub final_flags: Flags,
+    pub final_memory: Memory,
+    pub total_cycles: u64,
+    pub unhandled_ports: Vec<PortAccess>,
+    pub halt_reason: HaltReason,
+}
+
+impl SimulationResult {
+    /// The top `words` words of the stack after the run, the last pushed one first.
+    pub fn stack(&self, words: usize) -> Vec<u16> {
+        return read_stack(&self.final_memory, &self.final_status, words);
+    }
+}
+
+// The register or memory location an instruction wrote to, with its value before and after.
+struct DestinationChange {
+    destination: String,
+    start: u16,
+    end: u16,
 }
 
 #[derive(Hash, Copy, Clone)]
@@ -20,339 +76,2552 @@ pub struct Register {
     pub high: u8,
 }
 
-enum Source {
-    Register(Register),
-    Immediate(u16),
-}
-
 enum RegisterMode {
     High,
     Low,
     Universal,
 }
 impl RegisterMode {
-    fn parse(val: &str) -> Option<RegisterMode> {
-        match val.to_lowercase().as_str() {
-            "ah" | "bh" | "ch" | "dh" => Some(RegisterMode::High),
-            "al" | "bl" | "cl" | "dl" => Some(RegisterMode::Low),
-            "ax" | "bx" | "cx" | "dx" | "sp" | "bp" | "si" | "di" | "ss" | "cs" | "ds" | "es" => {
-                Some(RegisterMode::Universal)
-            }
+    fn parse(val: &Operand) -> Option<RegisterMode> {
+        match val {
+            Operand::SubRegister(reg) if reg.is_high() => Some(RegisterMode::High),
+            Operand::SubRegister(_) => Some(RegisterMode::Low),
+            Operand::Register(_) | Operand::Segment(_) => Some(RegisterMode::Universal),
             _ => None,
         }
     }
 }
 
-fn get_parent_register(reg: &str) -> String {
+fn get_parent_register(reg: &Operand) -> Option<String> {
     match reg {
-        "ah" | "al" => "ax".to_string(),
-        "bh" | "bl" => "bx".to_string(),
-        "ch" | "cl" => "cx".to_string(),
-        "dh" | "dl" => "dx".to_string(),
-        _ => reg.to_string(),
+        Operand::Register(reg) => Some(reg.name().to_string()),
+        Operand::SubRegister(reg) => Some(reg.parent().name().to_string()),
+        Operand::Segment(reg) => Some(reg.name().to_string()),
+        _ => None,
     }
 }
 
-fn get_source(registers: &HashMap<String, Register>, source: &str) -> Source {
-    if source.starts_with("0x") {
-        let without_prefix = &source[2..];
-        return Source::Immediate(u16::from_str_radix(without_prefix, 16).unwrap());
-    }
-    match source.parse::<i16>() {
-        Ok(immediate) => Source::Immediate(immediate as u16),
-        Err(_) => {
-            let source_parent = match RegisterMode::parse(source).expect("ERROR: Invalid register!")
-            {
-                RegisterMode::High | RegisterMode::Low => get_parent_register(source),
-                RegisterMode::Universal => source.to_string(),
-            };
-
-            let source_register = registers
-                .get(source_parent.as_str())
-                .expect("ERROR: Register not found!");
-            return Source::Register(Register {
-                high: source_register.high,
-                low: source_register.low,
-            });
-        }
-    }
-}
 pub fn get_register_value(reg: &Register) -> u16 {
     return ((reg.high as u16) << 8) | (reg.low as u16);
 }
 
-pub fn get_source_part(reg: &str, value: &Register) -> u8 {
-    let source_register_mode = RegisterMode::parse(reg).expect("ERROR: Source must be a register");
-    match source_register_mode {
-        RegisterMode::High => value.high,
-        RegisterMode::Low | RegisterMode::Universal => value.low,
+fn read_stack(memory: &Memory, registers: &HashMap<String, Register>, words: usize) -> Vec<u16> {
+    let stack_segment = get_register_value(&registers["ss"]);
+    let sp = get_register_value(&registers["sp"]);
+    return (0..words)
+        .map(|idx| {
+            let offset = sp.wrapping_add(2 * idx as u16);
+            memory.read(stack_segment, offset, Width::Word)
+        })
+        .collect();
+}
+
+// Raised by div and idiv when dividing by zero, or when the quotient doesn't fit.
+const DIVIDE_ERROR: u8 = 0;
+const BREAKPOINT: u8 = 3;
+const OVERFLOW: u8 = 4;
+
+fn invalid_operand(instruction: &DecodedArgument) -> Error {
+    return Error::InvalidOperand {
+        offset: instruction.offset,
+        opcode: instruction.opcode,
+    };
+}
+
+// Splits an instruction into its destination and source, failing if one is missing.
+fn binary_operands(instruction: &DecodedArgument) -> Result<(Operand, Operand)> {
+    match (instruction.destination, instruction.source) {
+        (Some(destination), Some(source)) => Ok((destination, source)),
+        _ => Err(invalid_operand(instruction)),
     }
 }
-pub fn execute_register_register_mov(
-    instruction: &DecodedArgument,
-    registers: &mut HashMap<String, Register>,
-) -> ExecutedOperation {
-    let source = get_source(&registers, &instruction.source);
-
-    let destination_key = get_parent_register(instruction.destination.as_str());
-    let destination = registers
-        .get_mut(&destination_key)
-        .expect("ERROR: Destination register not in Register set.");
-    let destination_starting_value = get_register_value(&destination);
-
-    let register_mode = RegisterMode::parse(instruction.destination.as_str())
-        .expect("ERROR: Destination must be a register!");
-    match register_mode {
-        RegisterMode::High => {
-            destination.high = match source {
-                Source::Register(reg) => get_source_part(&instruction.source, &reg),
-                Source::Immediate(immed) => immed as u8,
-            };
+
+fn jump_target(instruction: &DecodedArgument, ip: u16) -> Result<u16> {
+    match instruction.destination {
+        Some(Operand::Relative(displacement)) => Ok(ip.wrapping_add(displacement as u16)),
+        _ => Err(invalid_operand(instruction)),
+    }
+}
+
+/// The simulated processor: registers, IP, flags and the memory it runs on.
+pub struct Cpu {
+    registers: HashMap<String, Register>,
+    ip: u16,
+    flags: Flags,
+    memory: Memory,
+    io: IoBus,
+    timing: TimingModel,
+    instruction_table: Vec<(u8, InstructionLookup)>,
+    // Set when SP wraps around during the current step.
+    stack_wrapped: bool,
+    call_depth: u32,
+    // Set by hlt, and by interrupts without a handler.
+    halt_reason: Option<HaltReason>,
+}
+
+impl Cpu {
+    /// Creates a CPU with all registers and flags cleared, running on the given memory.
+    /// No devices are attached to its I/O ports.
+    pub fn new(memory: Memory, timing: TimingModel) -> Cpu {
+        return Cpu {
+            registers: HashMap::from([
+                (String::from("ax"), Register { low: 0, high: 0 }),
+                (String::from("bx"), Register { low: 0, high: 0 }),
+                (String::from("cx"), Register { low: 0, high: 0 }),
+                (String::from("dx"), Register { low: 0, high: 0 }),
+                (String::from("sp"), Register { low: 0, high: 0 }),
+                (String::from("bp"), Register { low: 0, high: 0 }),
+                (String::from("si"), Register { low: 0, high: 0 }),
+                (String::from("di"), Register { low: 0, high: 0 }),
+                (String::from("cs"), Register { low: 0, high: 0 }),
+                (String::from("ss"), Register { low: 0, high: 0 }),
+                (String::from("ds"), Register { low: 0, high: 0 }),
+                (String::from("es"), Register { low: 0, high: 0 }),
+            ]),
+            ip: 0,
+            flags: Flags::default(),
+            memory,
+            io: IoBus::new(),
+            timing,
+            instruction_table: generate_instruction_table(),
+            stack_wrapped: false,
+            call_depth: 0,
+            halt_reason: None,
+        };
+    }
+
+    pub fn register(&self, reg: WordRegister) -> u16 {
+        return get_register_value(&self.registers[reg.name()]);
+    }
+
+    pub fn set_register(&mut self, reg: WordRegister, value: u16) {
+        self.set_word(reg.name(), value);
+    }
+
+    pub fn segment(&self, reg: SegmentRegister) -> u16 {
+        return get_register_value(&self.registers[reg.name()]);
+    }
+
+    pub fn set_segment(&mut self, reg: SegmentRegister, value: u16) {
+        self.set_word(reg.name(), value);
+    }
+
+    fn set_word(&mut self, name: &str, value: u16) -> DestinationChange {
+        let register = Register {
+            low: value as u8,
+            high: (value >> 8) as u8,
+        };
+        let start = self
+            .registers
+            .insert(name.to_string(), register)
+            .map_or(0, |previous| get_register_value(&previous));
+        return DestinationChange {
+            destination: name.to_string(),
+            start,
+            end: value,
+        };
+    }
+
+    /// All general purpose and segment registers, by name.
+    pub fn registers(&self) -> &HashMap<String, Register> {
+        &self.registers
+    }
+
+    pub fn ip(&self) -> u16 {
+        self.ip
+    }
+
+    pub fn set_ip(&mut self, ip: u16) {
+        self.ip = ip;
+    }
+
+    pub fn flags(&self) -> Flags {
+        self.flags
+    }
+
+    pub fn set_flags(&mut self, flags: Flags) {
+        self.flags = flags;
+    }
+
+    /// Whether the CPU executed hlt. Without interrupt sources, only `resume` wakes it up again.
+    /// An interrupt without a handler halts it too.
+    pub fn halted(&self) -> bool {
+        return self.halt_reason.is_some();
+    }
+
+    pub fn halt_reason(&self) -> Option<HaltReason> {
+        return self.halt_reason;
+    }
+
+    /// Wakes the CPU up, e.g. after installing the handler of an unhandled interrupt.
+    /// It continues at CS:IP, which is past the instruction that halted it.
+    pub fn resume(&mut self) {
+        self.halt_reason = None;
+    }
+
+    /// Number of calls that haven't returned yet. Returning without a call leaves it at 0.
+    pub fn call_depth(&self) -> u32 {
+        self.call_depth
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    pub fn io(&self) -> &IoBus {
+        &self.io
+    }
+
+    /// Gives access to the I/O bus, to attach devices to it.
+    pub fn io_mut(&mut self) -> &mut IoBus {
+        &mut self.io
+    }
+
+    /// Copies machine code to CS:0 and points IP at it.
+    pub fn load_program(&mut self, code: &[u8]) {
+        let code_segment = self.segment(SegmentRegister::Cs);
+        self.memory
+            .load(Memory::physical_address(code_segment, 0), code);
+        self.ip = 0;
+    }
+
+    /// Decodes the instruction at CS:IP and executes it. Errors carry the physical address of the instruction.
+    /// A halted CPU doesn't execute anything until it's resumed.
+    pub fn step(&mut self) -> Result<ExecutedOperation> {
+        let address = Memory::physical_address(self.segment(SegmentRegister::Cs), self.ip);
+        if self.halted() {
+            return Err(Error::Halted { offset: address });
         }
+        self.stack_wrapped = false;
+        let call_depth = self.call_depth;
+        let instruction = decode_next(&self.instruction_table, self.memory.bytes(), address)?;
+        let ip_start = self.ip;
+        self.ip = self.ip.wrapping_add(instruction.byte_count as u16);
+        // Has to be calculated before executing, the instruction might change the registers it uses.
+        let memory_offset = [instruction.destination, instruction.source]
+            .iter()
+            .flatten()
+            .find_map(|operand| match operand {
+                Operand::Memory(address) => Some(self.effective_address(address).1),
+                _ => None,
+            });
+        // Shifts by cl take longer the more bits they move, repeated string instructions
+        // the more elements they work on.
+        let mut repetitions = match instruction.source {
+            Some(source) if instruction.opcode.is_shift() => {
+                self.read_instruction_operand(&instruction, &source)? as u8 as u32
+            }
+            _ => 0,
+        };
+        // Conditional jumps, loops and into take longer when they transfer control.
+        let mut jump_taken = false;
 
-        RegisterMode::Low => {
-            destination.low = match source {
-                Source::Register(reg) => get_source_part(&instruction.source, &reg),
-                Source::Immediate(immed) => immed as u8,
-            };
+        let change = match instruction.opcode {
+            Opcode::Mov => Some(self.execute_mov(&instruction)?),
+            Opcode::Xchg => Some(self.execute_exchange(&instruction)?),
+            Opcode::Lea | Opcode::Lds | Opcode::Les => {
+                Some(self.execute_load_address(&instruction)?)
+            }
+            Opcode::Xlat => {
+                let segment = instruction.segment_override.unwrap_or(SegmentRegister::Ds);
+                let al = self.register(WordRegister::Ax) & 0xff;
+                let offset = self.register(WordRegister::Bx).wrapping_add(al);
+                let value = self.memory.read(self.segment(segment), offset, Width::Byte);
+                self.write_operand(&Operand::SubRegister(ByteRegister::Al), Width::Byte, value)
+            }
+            Opcode::Lahf => {
+                let status = self.flags.bits & 0xff;
+                self.write_operand(&Operand::SubRegister(ByteRegister::Ah), Width::Byte, status)
+            }
+            Opcode::Sahf => {
+                // Only SF, ZF, AF, PF and CF come from ah.
+                let ah = self.register(WordRegister::Ax) >> 8;
+                self.flags.bits = (self.flags.bits & 0xff00) | (ah & 0xd5);
+                None
+            }
+            Opcode::Cbw => {
+                let al = self.register(WordRegister::Ax) as u8;
+                Some(self.set_word(WordRegister::Ax.name(), al as i8 as i16 as u16))
+            }
+            Opcode::Cwd => {
+                let negative = self.register(WordRegister::Ax) & 0x8000 != 0;
+                let high = if negative { 0xffff } else { 0 };
+                Some(self.set_word(WordRegister::Dx.name(), high))
+            }
+            Opcode::In => Some(self.execute_in(&instruction)?),
+            Opcode::Out => {
+                self.execute_out(&instruction)?;
+                None
+            }
+            Opcode::Add | Opcode::Adc | Opcode::Sub | Opcode::Sbb | Opcode::Cmp => {
+                self.execute_arithmetic(&instruction)?
+            }
+            Opcode::And | Opcode::Or | Opcode::Xor | Opcode::Test => {
+                self.execute_logic(&instruction)?
+            }
+            Opcode::Not | Opcode::Neg | Opcode::Inc | Opcode::Dec => {
+                Some(self.execute_unary(&instruction)?)
+            }
+            Opcode::Daa | Opcode::Das => Some(self.execute_decimal_adjust(instruction.opcode)),
+            Opcode::Aaa | Opcode::Aas => Some(self.execute_ascii_adjust(instruction.opcode)),
+            Opcode::Aam | Opcode::Aad => self.execute_ascii_adjust_base(&instruction)?,
+            Opcode::Mul | Opcode::Imul => Some(self.execute_multiply(&instruction)?),
+            Opcode::Div | Opcode::Idiv => self.execute_divide(&instruction)?,
+            Opcode::Push => Some(self.execute_push(&instruction)?),
+            Opcode::Pop => Some(self.execute_pop(&instruction)?),
+            Opcode::Pushf => Some(self.push(self.flags.bits)),
+            Opcode::Popf => {
+                self.flags.bits = self.pop();
+                None
+            }
+            Opcode::Call | Opcode::CallFar => Some(self.execute_call(&instruction)?),
+            Opcode::Ret | Opcode::Retf => Some(self.execute_return(&instruction)?),
+            Opcode::Jmp => {
+                self.ip = self.near_target(&instruction)?;
+                None
+            }
+            Opcode::JmpFar => {
+                let (segment, offset) = self.far_target(&instruction)?;
+                self.set_segment(SegmentRegister::Cs, segment);
+                self.ip = offset;
+                None
+            }
+            Opcode::Clc
+            | Opcode::Stc
+            | Opcode::Cmc
+            | Opcode::Cld
+            | Opcode::Std
+            | Opcode::Cli
+            | Opcode::Sti => {
+                self.execute_flag_instruction(instruction.opcode);
+                None
+            }
+            Opcode::Hlt => {
+                self.halt_reason = Some(HaltReason::Hlt);
+                None
+            }
+            // There is no coprocessor to wait for, and only one processor on the bus.
+            Opcode::Nop | Opcode::Wait => None,
+            Opcode::Int | Opcode::Int3 | Opcode::Into => {
+                let (change, taken) = self.execute_interrupt(&instruction)?;
+                jump_taken = taken;
+                change
+            }
+            Opcode::Iret => Some(self.execute_interrupt_return()),
+            Opcode::Shl
+            | Opcode::Shr
+            | Opcode::Sar
+            | Opcode::Rol
+            | Opcode::Ror
+            | Opcode::Rcl
+            | Opcode::Rcr => Some(self.execute_shift(&instruction)?),
+            Opcode::Movs | Opcode::Cmps | Opcode::Scas | Opcode::Lods | Opcode::Stos => {
+                let (change, count) = self.execute_string(&instruction);
+                repetitions = count;
+                change
+            }
+            Opcode::Loop | Opcode::Loopz | Opcode::Loopnz => {
+                let (change, taken) = self.execute_loop(&instruction)?;
+                jump_taken = taken;
+                Some(change)
+            }
+            Opcode::Je
+            | Opcode::Jne
+            | Opcode::Jl
+            | Opcode::Jnl
+            | Opcode::Jle
+            | Opcode::Jnle
+            | Opcode::Jb
+            | Opcode::Jnb
+            | Opcode::Jbe
+            | Opcode::Jnbe
+            | Opcode::Jp
+            | Opcode::Jnp
+            | Opcode::Jo
+            | Opcode::Jno
+            | Opcode::Js
+            | Opcode::Jns
+            | Opcode::Jcxz => {
+                jump_taken = self.execute_jump(&instruction)?;
+                None
+            }
+        };
+
+        let cycles = instruction_cycles(
+            &instruction,
+            self.timing,
+            memory_offset,
+            jump_taken,
+            repetitions,
+        );
+
+        let (dest_reg, dest_start, dest_end) = match change {
+            Some(change) => (Some(change.destination), change.start, change.end),
+            None => (None, 0, 0),
+        };
+        return Ok(ExecutedOperation {
+            instruction,
+            dest_reg,
+            dest_start,
+            dest_end,
+            ip_start,
+            ip_end: self.ip,
+            cycles,
+            stack_wrapped: self.stack_wrapped,
+            call_depth,
+        });
+    }
+
+    /// Computes segment:offset of a memory operand. Addressing through bp defaults to
+    /// the stack segment, everything else to the data segment, unless a prefix overrides it.
+    fn effective_address(&self, address: &EffectiveAddress) -> (u16, u16) {
+        let mut offset = address.displacement as u16;
+        for reg in [address.base, address.index].iter().flatten() {
+            offset = offset.wrapping_add(self.register(*reg));
         }
+        let segment = match address.segment {
+            Some(segment) => segment,
+            None if address.base == Some(WordRegister::Bp) => SegmentRegister::Ss,
+            None => SegmentRegister::Ds,
+        };
+        return (self.segment(segment), offset);
+    }
 
-        RegisterMode::Universal => {
-            destination.high = match source {
-                Source::Register(reg) => reg.high,
-                Source::Immediate(immed) => {
-                    let high_mask = u16::MAX - 255;
-                    let high_masked = immed & high_mask;
-                    (high_masked >> 8) as u8
-                }
-            };
+    /// Reads a register, immediate or memory operand. Sub-registers only yield their own byte.
+    /// Returns `None` for operands that have no value, like relative jump targets.
+    fn read_operand(&self, operand: &Operand, width: Width) -> Option<u16> {
+        match operand {
+            Operand::Immediate(immediate) => return Some(*immediate),
+            Operand::Memory(address) => {
+                let (segment, offset) = self.effective_address(address);
+                return Some(self.memory.read(segment, offset, width));
+            }
+            _ => (),
+        }
+        let register = self.registers.get(get_parent_register(operand)?.as_str())?;
+        match RegisterMode::parse(operand)? {
+            RegisterMode::High => Some(register.high as u16),
+            RegisterMode::Low => Some(register.low as u16),
+            RegisterMode::Universal => Some(get_register_value(register)),
+        }
+    }
 
-            destination.low = match source {
-                Source::Register(reg) => reg.low,
-                Source::Immediate(immed) => immed as u8,
-            };
+    /// Writes to a register or memory operand. Sub-registers only take the low byte of `value`,
+    /// and leave the other half of their parent untouched. Returns `None` for operands
+    /// that can't be written to, like immediates.
+    fn write_operand(
+        &mut self,
+        operand: &Operand,
+        width: Width,
+        value: u16,
+    ) -> Option<DestinationChange> {
+        if let Operand::Memory(address) = operand {
+            let (segment, offset) = self.effective_address(address);
+            let start = self.memory.read(segment, offset, width);
+            self.memory.write(segment, offset, width, value);
+            return Some(DestinationChange {
+                destination: operand.to_string(),
+                start,
+                end: self.memory.read(segment, offset, width),
+            });
         }
+
+        let destination_key = get_parent_register(operand)?;
+        let mode = RegisterMode::parse(operand)?;
+        let destination = self.registers.get_mut(&destination_key)?;
+        let destination_starting_value = get_register_value(destination);
+
+        match mode {
+            RegisterMode::High => destination.high = value as u8,
+            RegisterMode::Low => destination.low = value as u8,
+            RegisterMode::Universal => {
+                destination.high = (value >> 8) as u8;
+                destination.low = value as u8;
+            }
+        }
+        return Some(DestinationChange {
+            destination: destination_key,
+            start: destination_starting_value,
+            end: get_register_value(destination),
+        });
     }
-    return ExecutedOperation {
-        dest_reg: destination_key,
-        dest_start: destination_starting_value,
-        dest_end: get_register_value(destination),
-    };
-}
 
-pub fn execute_instructions(instructions: &Vec<DecodedArgument>) -> Result<SimulationResult> {
-    let mut registers: HashMap<String, Register> = HashMap::from([
-        (String::from("ax"), Register { low: 0, high: 0 }),
-        (String::from("bx"), Register { low: 0, high: 0 }),
-        (String::from("cx"), Register { low: 0, high: 0 }),
-        (String::from("dx"), Register { low: 0, high: 0 }),
-        (String::from("sp"), Register { low: 0, high: 0 }),
-        (String::from("bp"), Register { low: 0, high: 0 }),
-        (String::from("si"), Register { low: 0, high: 0 }),
-        (String::from("di"), Register { low: 0, high: 0 }),
-        (String::from("cs"), Register { low: 0, high: 0 }),
-        (String::from("ss"), Register { low: 0, high: 0 }),
-        (String::from("ds"), Register { low: 0, high: 0 }),
-        (String::from("es"), Register { low: 0, high: 0 }),
-    ]);
-
-    let mut flags: [u8; 16] = [0; 16];
-    let mut execution_history: Vec<ExecutedOperation> = Vec::new();
+    // Same as `read_operand`, but fails with the instruction the operand belongs to.
+    fn read_instruction_operand(
+        &self,
+        instruction: &DecodedArgument,
+        operand: &Operand,
+    ) -> Result<u16> {
+        self.read_operand(operand, instruction.width)
+            .ok_or_else(|| invalid_operand(instruction))
+    }
+
+    // Same as `write_operand`, but fails with the instruction the operand belongs to.
+    fn write_instruction_operand(
+        &mut self,
+        instruction: &DecodedArgument,
+        operand: &Operand,
+        value: u16,
+    ) -> Result<DestinationChange> {
+        self.write_operand(operand, instruction.width, value)
+            .ok_or_else(|| invalid_operand(instruction))
+    }
+
+    fn execute_mov(&mut self, instruction: &DecodedArgument) -> Result<DestinationChange> {
+        let (destination, source) = binary_operands(instruction)?;
+        let value = self.read_instruction_operand(instruction, &source)?;
+        return self.write_instruction_operand(instruction, &destination, value);
+    }
+
+    /// Swaps the two operands. Reports the change of the destination.
+    fn execute_exchange(&mut self, instruction: &DecodedArgument) -> Result<DestinationChange> {
+        let (destination, source) = binary_operands(instruction)?;
+        let destination_value = self.read_instruction_operand(instruction, &destination)?;
+        let source_value = self.read_instruction_operand(instruction, &source)?;
+        self.write_instruction_operand(instruction, &source, destination_value)?;
+        return self.write_instruction_operand(instruction, &destination, source_value);
+    }
 
-    for instruction in instructions {
-        match instruction.operand.as_str() {
-            "mov" => {
-                execution_history.push(execute_register_register_mov(instruction, &mut registers));
+    /// Executes lea, lds and les. Lea only computes the offset of its memory operand, without
+    /// accessing memory. Lds and les load a far pointer from it, the offset into the register
+    /// and the segment into ds or es.
+    fn execute_load_address(&mut self, instruction: &DecodedArgument) -> Result<DestinationChange> {
+        let (destination, source) = binary_operands(instruction)?;
+        let address = match source {
+            Operand::Memory(address) => address,
+            _ => return Err(invalid_operand(instruction)),
+        };
+        let (segment, offset) = self.effective_address(&address);
+        let value = match instruction.opcode {
+            Opcode::Lea => offset,
+            _ => {
+                let pointer_segment =
+                    self.memory
+                        .read(segment, offset.wrapping_add(2), Width::Word);
+                let segment_register = match instruction.opcode {
+                    Opcode::Lds => SegmentRegister::Ds,
+                    _ => SegmentRegister::Es,
+                };
+                self.set_segment(segment_register, pointer_segment);
+                self.memory.read(segment, offset, Width::Word)
             }
-            "add" => {
-                execution_history.push(execute_register_register_add(instruction, &mut registers, &mut flags));
+        };
+        return self.write_instruction_operand(instruction, &destination, value);
+    }
+
+    /// Reads the accumulator from the port in the immediate or dx.
+    fn execute_in(&mut self, instruction: &DecodedArgument) -> Result<DestinationChange> {
+        let (destination, port) = binary_operands(instruction)?;
+        let port = self.read_instruction_operand(instruction, &port)?;
+        let value = self.io.read(port, instruction.width);
+        return self.write_instruction_operand(instruction, &destination, value);
+    }
+
+    /// Writes the accumulator to the port in the immediate or dx.
+    fn execute_out(&mut self, instruction: &DecodedArgument) -> Result<()> {
+        let (port, source) = binary_operands(instruction)?;
+        let port = self.read_instruction_operand(instruction, &port)?;
+        let value = self.read_instruction_operand(instruction, &source)?;
+        self.io.write(port, instruction.width, value);
+        return Ok(());
+    }
+
+    /// Executes add, adc, sub, sbb and cmp. The operation wraps at the instruction's width,
+    /// so byte operations never carry into the other half of a register. Adc and sbb also
+    /// add or subtract CF.
+    fn execute_arithmetic(
+        &mut self,
+        instruction: &DecodedArgument,
+    ) -> Result<Option<DestinationChange>> {
+        let (destination, source) = binary_operands(instruction)?;
+        let width = instruction.width;
+        let destination_value = self.read_instruction_operand(instruction, &destination)?;
+        let source_value = self.read_instruction_operand(instruction, &source)?;
+        let carry =
+            matches!(instruction.opcode, Opcode::Adc | Opcode::Sbb) && self.flags.get(Flags::CARRY);
+        match instruction.opcode {
+            Opcode::Add | Opcode::Adc => {
+                self.flags
+                    .set_adc_flags(destination_value, source_value, carry, width);
+                let result = destination_value
+                    .wrapping_add(source_value)
+                    .wrapping_add(carry as u16);
+                Ok(Some(self.write_instruction_operand(
+                    instruction,
+                    &destination,
+                    result,
+                )?))
             }
-            "sub" => {
-                execution_history.push(execute_register_register_sub(instruction, &mut registers, &mut flags));
+            Opcode::Sub | Opcode::Sbb => {
+                self.flags
+                    .set_sbb_flags(destination_value, source_value, carry, width);
+                let result = destination_value
+                    .wrapping_sub(source_value)
+                    .wrapping_sub(carry as u16);
+                Ok(Some(self.write_instruction_operand(
+                    instruction,
+                    &destination,
+                    result,
+                )?))
             }
-            "cmp" => {
-                execution_history.push(execute_register_register_cmp(instruction, &mut registers, &mut flags));
+            Opcode::Cmp => {
+                self.flags
+                    .set_sub_flags(destination_value, source_value, width);
+                Ok(None)
             }
-            _ => (),
+            _ => Err(Error::UnsupportedInstruction {
+                offset: instruction.offset,
+                opcode: instruction.opcode,
+            }),
         }
     }
-    return Ok(SimulationResult {
-        steps: execution_history,
-        final_status: registers,
-    });
-}
 
-fn execute_register_register_cmp(
-    instruction: &DecodedArgument,
-    registers: &HashMap<String, Register>,
-    flags: &mut [u8;16]
-) -> ExecutedOperation {
-    let source = get_source(&registers, &instruction.source);
-
-    let destination_key = get_parent_register(instruction.destination.as_str());
-    // Clone should dereference the value pair :C
-    let mut destination = registers
-        .get(&destination_key)
-        .expect("ERROR: Destination register not in Register set.")
-        .clone();
-    let destination_starting_value = get_register_value(&destination);
-
-    let register_mode = RegisterMode::parse(instruction.destination.as_str())
-        .expect("ERROR: Destination must be a register!");
-    match register_mode {
-        RegisterMode::High => {
-            destination.high -= match source {
-                Source::Register(reg) => get_source_part(&instruction.source, &reg),
-                Source::Immediate(immed) => immed as u8,
-            };
+    /// Executes and, or, xor and test. Test only sets the flags.
+    fn execute_logic(
+        &mut self,
+        instruction: &DecodedArgument,
+    ) -> Result<Option<DestinationChange>> {
+        let (destination, source) = binary_operands(instruction)?;
+        let destination_value = self.read_instruction_operand(instruction, &destination)?;
+        let source_value = self.read_instruction_operand(instruction, &source)?;
+        let result = match instruction.opcode {
+            Opcode::And | Opcode::Test => destination_value & source_value,
+            Opcode::Or => destination_value | source_value,
+            Opcode::Xor => destination_value ^ source_value,
+            _ => {
+                return Err(Error::UnsupportedInstruction {
+                    offset: instruction.offset,
+                    opcode: instruction.opcode,
+                })
+            }
+        };
+        self.flags.set_logic_flags(result, instruction.width);
+        if instruction.opcode == Opcode::Test {
+            return Ok(None);
         }
+        let change = self.write_instruction_operand(instruction, &destination, result)?;
+        return Ok(Some(change));
+    }
 
-        RegisterMode::Low => {
-            destination.low -= match source {
-                Source::Register(reg) => get_source_part(&instruction.source, &reg),
-                Source::Immediate(immed) => immed as u8,
-            };
+    /// Executes not, neg, inc and dec. Not leaves the flags alone, neg sets them like
+    /// `0 - destination`. Inc and dec set them like adding or subtracting 1, but keep CF.
+    fn execute_unary(&mut self, instruction: &DecodedArgument) -> Result<DestinationChange> {
+        let destination = instruction
+            .destination
+            .ok_or_else(|| invalid_operand(instruction))?;
+        let value = self.read_instruction_operand(instruction, &destination)?;
+        let result = match instruction.opcode {
+            Opcode::Neg => {
+                self.flags.set_sub_flags(0, value, instruction.width);
+                0u16.wrapping_sub(value)
+            }
+            Opcode::Inc | Opcode::Dec => {
+                let carry = self.flags.get(Flags::CARRY);
+                let result = if instruction.opcode == Opcode::Inc {
+                    self.flags.set_add_flags(value, 1, instruction.width);
+                    value.wrapping_add(1)
+                } else {
+                    self.flags.set_sub_flags(value, 1, instruction.width);
+                    value.wrapping_sub(1)
+                };
+                self.flags.set(Flags::CARRY, carry);
+                result
+            }
+            _ => !value,
+        };
+        return self.write_instruction_operand(instruction, &destination, result);
+    }
+
+    /// Executes mul and imul. The byte forms multiply al into ax, the word forms ax into dx:ax.
+    /// CF and OF are set when the upper half is needed, the other status flags are left alone.
+    fn execute_multiply(&mut self, instruction: &DecodedArgument) -> Result<DestinationChange> {
+        let operand = instruction
+            .destination
+            .ok_or_else(|| invalid_operand(instruction))?;
+        let source = self.read_instruction_operand(instruction, &operand)?;
+        let signed = instruction.opcode == Opcode::Imul;
+        let ax = self.register(WordRegister::Ax);
+
+        let (result, overflow) = match (instruction.width, signed) {
+            (Width::Byte, false) => {
+                let result = (ax as u8 as u32) * (source as u8 as u32);
+                (result, result > 0xff)
+            }
+            (Width::Byte, true) => {
+                let result = (ax as u8 as i8 as i32) * (source as u8 as i8 as i32);
+                (result as u32, result != result as i8 as i32)
+            }
+            (Width::Word, false) => {
+                let result = (ax as u32) * (source as u32);
+                (result, result > 0xffff)
+            }
+            (Width::Word, true) => {
+                let result = (ax as i16 as i32) * (source as i16 as i32);
+                (result as u32, result != result as i16 as i32)
+            }
+        };
+        self.flags.set(Flags::CARRY, overflow);
+        self.flags.set(Flags::OVERFLOW, overflow);
+        if instruction.width == Width::Word {
+            self.set_register(WordRegister::Dx, (result >> 16) as u16);
         }
+        return Ok(self.set_word(WordRegister::Ax.name(), result as u16));
+    }
 
-        RegisterMode::Universal => {
-            destination.high -= match source {
-                Source::Register(reg) => reg.high,
-                Source::Immediate(immed) => {
-                    let high_mask = u16::MAX - 255;
-                    let high_masked = immed & high_mask;
-                    (high_masked >> 8) as u8
-                }
-            };
+    /// Executes div and idiv. The byte forms divide ax into al, with the remainder in ah.
+    /// The word forms divide dx:ax into ax, with the remainder in dx. Dividing by zero, or
+    /// getting a quotient that doesn't fit, raises the divide error interrupt instead.
+    fn execute_divide(
+        &mut self,
+        instruction: &DecodedArgument,
+    ) -> Result<Option<DestinationChange>> {
+        let operand = instruction
+            .destination
+            .ok_or_else(|| invalid_operand(instruction))?;
+        let divisor = self.read_instruction_operand(instruction, &operand)?;
+        let signed = instruction.opcode == Opcode::Idiv;
+        let ax = self.register(WordRegister::Ax);
+        let dx = self.register(WordRegister::Dx);
 
-            destination.low -= match source {
-                Source::Register(reg) => reg.low,
-                Source::Immediate(immed) => immed as u8,
-            };
+        // The 8086 can't produce the most negative quotient, so signed ones go from -limit to limit.
+        let (dividend, divisor, limit): (i64, i64, i64) = match (instruction.width, signed) {
+            (Width::Byte, false) => (ax as i64, divisor as u8 as i64, 0xff),
+            (Width::Byte, true) => (ax as i16 as i64, divisor as u8 as i8 as i64, 0x7f),
+            (Width::Word, false) => {
+                let dividend = ((dx as u32) << 16) | ax as u32;
+                (dividend as i64, divisor as i64, 0xffff)
+            }
+            (Width::Word, true) => {
+                let dividend = ((dx as u32) << 16) | ax as u32;
+                (dividend as i32 as i64, divisor as i16 as i64, 0x7fff)
+            }
+        };
+        if divisor == 0 || (dividend / divisor).abs() > limit {
+            self.interrupt(DIVIDE_ERROR);
+            return Ok(None);
+        }
+
+        let quotient = (dividend / divisor) as u16;
+        let remainder = (dividend % divisor) as u16;
+        match instruction.width {
+            Width::Byte => {
+                let result = (remainder << 8) | (quotient & 0xff);
+                Ok(Some(self.set_word(WordRegister::Ax.name(), result)))
+            }
+            Width::Word => {
+                self.set_register(WordRegister::Dx, remainder);
+                Ok(Some(self.set_word(WordRegister::Ax.name(), quotient)))
+            }
         }
     }
 
-    set_flags(get_register_value(&destination), flags);
+    /// Executes daa and das, which turn al into packed BCD after an add or sub. The 8086 does this
+    /// by adding or subtracting 0x06 and 0x60, and OF comes out of that operation. A set AF also
+    /// raises the threshold for the high digit from 0x99 to 0x9f.
+    fn execute_decimal_adjust(&mut self, opcode: Opcode) -> DestinationChange {
+        let al = self.register(WordRegister::Ax) as u8;
+        let auxiliary_carry = self.flags.get(Flags::AUXILIARY_CARRY);
+        let carry = self.flags.get(Flags::CARRY);
 
-    return ExecutedOperation {
-        dest_reg: destination_key,
-        dest_start: destination_starting_value,
-        dest_end: get_register_value(&destination),
-    };
-}
+        let mut correction = 0u8;
+        if al & 0x0f > 9 || auxiliary_carry {
+            correction |= 0x06;
+        }
+        let threshold = if auxiliary_carry { 0x9f } else { 0x99 };
+        if al > threshold || carry {
+            correction |= 0x60;
+        }
+        let result = if opcode == Opcode::Daa {
+            al.wrapping_add(correction)
+        } else {
+            al.wrapping_sub(correction)
+        };
+        let overflow = if opcode == Opcode::Daa {
+            al & 0x80 == 0 && result & 0x80 != 0
+        } else {
+            al & 0x80 != 0 && result & 0x80 == 0
+        };
 
-fn execute_register_register_add(
-    instruction: &DecodedArgument,
-    registers: &mut HashMap<String, Register>,
-    flags: &mut [u8;16]
-) -> ExecutedOperation {
-    let source = get_source(&registers, &instruction.source);
-
-    let destination_key = get_parent_register(instruction.destination.as_str());
-    let destination = registers
-        .get_mut(&destination_key)
-        .expect("ERROR: Destination register not in Register set.");
-    let destination_starting_value = get_register_value(&destination);
-
-    let register_mode = RegisterMode::parse(instruction.destination.as_str())
-        .expect("ERROR: Destination must be a register!");
-    match register_mode {
-        RegisterMode::High => {
-            destination.high += match source {
-                Source::Register(reg) => get_source_part(&instruction.source, &reg),
-                Source::Immediate(immed) => immed as u8,
+        // The 0x06 step can carry or borrow out of al on its own, like 0x03 - 0x06 in das.
+        let low_carry = correction & 0x06 != 0
+            && if opcode == Opcode::Daa {
+                al > 0xf9
+            } else {
+                al < 0x06
             };
+
+        self.flags
+            .set(Flags::AUXILIARY_CARRY, correction & 0x06 != 0);
+        self.flags
+            .set(Flags::CARRY, correction & 0x60 != 0 || low_carry);
+        self.flags.set(Flags::OVERFLOW, overflow);
+        self.flags.set_result_flags(result as u16, Width::Byte);
+        let ax = (self.register(WordRegister::Ax) & 0xff00) | result as u16;
+        return self.set_word(WordRegister::Ax.name(), ax);
+    }
+
+    /// Executes aaa and aas, which turn al into an unpacked BCD digit after an add or sub,
+    /// carrying into ah. The 8086 adds or subtracts 6 on al alone, and sets SF, ZF, PF and OF
+    /// from that before clearing the high nibble.
+    fn execute_ascii_adjust(&mut self, opcode: Opcode) -> DestinationChange {
+        let ax = self.register(WordRegister::Ax);
+        let (mut ah, al) = ((ax >> 8) as u8, ax as u8);
+        let adjust = al & 0x0f > 9 || self.flags.get(Flags::AUXILIARY_CARRY);
+        let correction = if adjust { 6 } else { 0 };
+
+        let result = if opcode == Opcode::Aaa {
+            self.flags.set_add_flags(al as u16, correction, Width::Byte);
+            ah = ah.wrapping_add(adjust as u8);
+            al.wrapping_add(correction as u8)
+        } else {
+            self.flags.set_sub_flags(al as u16, correction, Width::Byte);
+            ah = ah.wrapping_sub(adjust as u8);
+            al.wrapping_sub(correction as u8)
+        };
+        self.flags.set(Flags::AUXILIARY_CARRY, adjust);
+        self.flags.set(Flags::CARRY, adjust);
+        let ax = ((ah as u16) << 8) | (result & 0x0f) as u16;
+        return self.set_word(WordRegister::Ax.name(), ax);
+    }
+
+    /// Executes aam, which splits al into ah = al / base and al = al % base, and aad, which
+    /// joins them back into al = ah * base + al. Aam by 0 raises the divide error interrupt.
+    /// Aam sets the flags like a logical operation on al, aad like the final add.
+    fn execute_ascii_adjust_base(
+        &mut self,
+        instruction: &DecodedArgument,
+    ) -> Result<Option<DestinationChange>> {
+        let base = match instruction.destination {
+            Some(Operand::Immediate(base)) => base as u8,
+            _ => return Err(invalid_operand(instruction)),
+        };
+        let ax = self.register(WordRegister::Ax);
+        let (ah, al) = ((ax >> 8) as u8, ax as u8);
+
+        let result = if instruction.opcode == Opcode::Aam {
+            if base == 0 {
+                self.interrupt(DIVIDE_ERROR);
+                return Ok(None);
+            }
+            let remainder = al % base;
+            self.flags.set_logic_flags(remainder as u16, Width::Byte);
+            (((al / base) as u16) << 8) | remainder as u16
+        } else {
+            let product = ah.wrapping_mul(base);
+            self.flags
+                .set_add_flags(al as u16, product as u16, Width::Byte);
+            al.wrapping_add(product) as u16
+        };
+        return Ok(Some(self.set_word(WordRegister::Ax.name(), result)));
+    }
+
+    /// The top `words` words of the stack, the last pushed one first.
+    pub fn stack(&self, words: usize) -> Vec<u16> {
+        return read_stack(&self.memory, &self.registers, words);
+    }
+
+    fn push(&mut self, value: u16) -> DestinationChange {
+        let sp = self.register(WordRegister::Sp);
+        if sp < 2 {
+            self.stack_wrapped = true;
         }
+        let change = self.set_word(WordRegister::Sp.name(), sp.wrapping_sub(2));
+        let stack_segment = self.segment(SegmentRegister::Ss);
+        self.memory
+            .write(stack_segment, change.end, Width::Word, value);
+        return change;
+    }
 
-        RegisterMode::Low => {
-            destination.low += match source {
-                Source::Register(reg) => get_source_part(&instruction.source, &reg),
-                Source::Immediate(immed) => immed as u8,
-            };
+    fn pop(&mut self) -> u16 {
+        let sp = self.register(WordRegister::Sp);
+        if sp > 0xfffd {
+            self.stack_wrapped = true;
         }
+        let stack_segment = self.segment(SegmentRegister::Ss);
+        let value = self.memory.read(stack_segment, sp, Width::Word);
+        self.set_register(WordRegister::Sp, sp.wrapping_add(2));
+        return value;
+    }
 
-        RegisterMode::Universal => {
-            destination.high += match source {
-                Source::Register(reg) => reg.high,
-                Source::Immediate(immed) => {
-                    let high_mask = u16::MAX - 255;
-                    let high_masked = immed & high_mask;
-                    (high_masked >> 8) as u8
-                }
-            };
+    fn execute_push(&mut self, instruction: &DecodedArgument) -> Result<DestinationChange> {
+        let operand = instruction
+            .destination
+            .ok_or_else(|| invalid_operand(instruction))?;
+        let value = match operand {
+            // The 8086 pushes the value sp has after the push.
+            Operand::Register(WordRegister::Sp) => self.register(WordRegister::Sp).wrapping_sub(2),
+            _ => self.read_instruction_operand(instruction, &operand)?,
+        };
+        return Ok(self.push(value));
+    }
 
-            destination.low += match source {
-                Source::Register(reg) => reg.low,
-                Source::Immediate(immed) => immed as u8,
-            };
+    fn execute_pop(&mut self, instruction: &DecodedArgument) -> Result<DestinationChange> {
+        let operand = instruction
+            .destination
+            .ok_or_else(|| invalid_operand(instruction))?;
+        let value = self.pop();
+        return self.write_instruction_operand(instruction, &operand, value);
+    }
+
+    /// The offset a near jump or call goes to: relative to the next instruction,
+    /// or an absolute one in a register or memory.
+    fn near_target(&self, instruction: &DecodedArgument) -> Result<u16> {
+        match instruction.destination {
+            Some(Operand::Relative(displacement)) => Ok(self.ip.wrapping_add(displacement as u16)),
+            Some(operand) => self.read_instruction_operand(instruction, &operand),
+            None => Err(invalid_operand(instruction)),
         }
     }
 
-    set_flags(get_register_value(destination), flags);
+    /// The segment and offset a far jump or call goes to: a direct address,
+    /// or an offset followed by a segment in memory.
+    fn far_target(&self, instruction: &DecodedArgument) -> Result<(u16, u16)> {
+        match instruction.destination {
+            Some(Operand::Far { segment, offset }) => Ok((segment, offset)),
+            Some(Operand::Memory(address)) => {
+                let (segment, offset) = self.effective_address(&address);
+                let target = self.memory.read(segment, offset, Width::Word);
+                let target_segment = self
+                    .memory
+                    .read(segment, offset.wrapping_add(2), Width::Word);
+                Ok((target_segment, target))
+            }
+            _ => Err(invalid_operand(instruction)),
+        }
+    }
 
-    return ExecutedOperation {
-        dest_reg: destination_key,
-        dest_start: destination_starting_value,
-        dest_end: get_register_value(destination),
-    };
-}
+    fn stack_pointer_change(&self, start: u16) -> DestinationChange {
+        return DestinationChange {
+            destination: WordRegister::Sp.name().to_string(),
+            start,
+            end: self.register(WordRegister::Sp),
+        };
+    }
 
-fn execute_register_register_sub(
-    instruction: &DecodedArgument,
-    registers: &mut HashMap<String, Register>,
-    flags: &mut [u8;16]
-) -> ExecutedOperation {
-    let source = get_source(&registers, &instruction.source);
-
-    let destination_key = get_parent_register(instruction.destination.as_str());
-    let destination = registers
-        .get_mut(&destination_key)
-        .expect("ERROR: Destination register not in Register set.");
-    let destination_starting_value = get_register_value(&destination);
-
-    let register_mode = RegisterMode::parse(instruction.destination.as_str())
-        .expect("ERROR: Destination must be a register!");
-    match register_mode {
-        RegisterMode::High => {
-            destination.high -= match source {
-                Source::Register(reg) => get_source_part(&instruction.source, &reg),
-                Source::Immediate(immed) => immed as u8,
-            };
+    /// Pushes the return address and jumps to the target. Far calls push CS before IP.
+    fn execute_call(&mut self, instruction: &DecodedArgument) -> Result<DestinationChange> {
+        let sp = self.register(WordRegister::Sp);
+        if instruction.opcode == Opcode::CallFar {
+            let (segment, offset) = self.far_target(instruction)?;
+            self.push(self.segment(SegmentRegister::Cs));
+            self.push(self.ip);
+            self.set_segment(SegmentRegister::Cs, segment);
+            self.ip = offset;
+        } else {
+            let target = self.near_target(instruction)?;
+            self.push(self.ip);
+            self.ip = target;
         }
+        self.call_depth += 1;
+        return Ok(self.stack_pointer_change(sp));
+    }
 
-        RegisterMode::Low => {
-            destination.low -= match source {
-                Source::Register(reg) => get_source_part(&instruction.source, &reg),
-                Source::Immediate(immed) => immed as u8,
-            };
+    /// Pops the return address, and CS for retf. `ret n` releases another n bytes of stack.
+    fn execute_return(&mut self, instruction: &DecodedArgument) -> Result<DestinationChange> {
+        let sp = self.register(WordRegister::Sp);
+        let release = match instruction.destination {
+            Some(Operand::Immediate(bytes)) => bytes,
+            None => 0,
+            _ => return Err(invalid_operand(instruction)),
+        };
+        self.ip = self.pop();
+        if instruction.opcode == Opcode::Retf {
+            let code_segment = self.pop();
+            self.set_segment(SegmentRegister::Cs, code_segment);
+        }
+        let released = self.register(WordRegister::Sp).wrapping_add(release);
+        self.set_register(WordRegister::Sp, released);
+        self.call_depth = self.call_depth.saturating_sub(1);
+        return Ok(self.stack_pointer_change(sp));
+    }
+
+    /// Pushes FLAGS, CS and IP, clears IF and TF, and continues at the handler
+    /// the interrupt vector table at physical address 0 points to. The handler counts
+    /// as a call until its iret.
+    fn interrupt(&mut self, vector: u8) {
+        let entry = vector as u16 * 4;
+        let handler = self.memory.read(0, entry, Width::Word);
+        let code_segment = self.memory.read(0, entry + 2, Width::Word);
+        // The vector table itself lives at 0000:0000, so no handler can start there.
+        if (code_segment, handler) == (0, 0) {
+            self.halt_reason = Some(HaltReason::UnhandledInterrupt(vector));
+            return;
+        }
+
+        self.push(self.flags.bits);
+        self.push(self.segment(SegmentRegister::Cs));
+        self.push(self.ip);
+        self.flags.set(Flags::INTERRUPT, false);
+        self.flags.set(Flags::TRAP, false);
+
+        self.ip = handler;
+        self.set_segment(SegmentRegister::Cs, code_segment);
+        self.call_depth += 1;
+    }
+
+    /// Executes the instructions that set, clear or complement a single flag.
+    fn execute_flag_instruction(&mut self, opcode: Opcode) {
+        match opcode {
+            Opcode::Clc => self.flags.set(Flags::CARRY, false),
+            Opcode::Stc => self.flags.set(Flags::CARRY, true),
+            Opcode::Cmc => self.flags.set(Flags::CARRY, !self.flags.get(Flags::CARRY)),
+            Opcode::Cld => self.flags.set(Flags::DIRECTION, false),
+            Opcode::Std => self.flags.set(Flags::DIRECTION, true),
+            Opcode::Cli => self.flags.set(Flags::INTERRUPT, false),
+            Opcode::Sti => self.flags.set(Flags::INTERRUPT, true),
+            _ => (),
+        }
+    }
+
+    /// Executes int n, int3 and into. Into only interrupts when OF is set.
+    /// Returns the change to sp, and whether the interrupt happened.
+    fn execute_interrupt(
+        &mut self,
+        instruction: &DecodedArgument,
+    ) -> Result<(Option<DestinationChange>, bool)> {
+        let vector = match (instruction.opcode, instruction.destination) {
+            (Opcode::Int, Some(Operand::Immediate(vector))) => vector as u8,
+            (Opcode::Int3, None) => BREAKPOINT,
+            (Opcode::Into, None) if self.flags.get(Flags::OVERFLOW) => OVERFLOW,
+            (Opcode::Into, None) => return Ok((None, false)),
+            _ => return Err(invalid_operand(instruction)),
+        };
+        let sp = self.register(WordRegister::Sp);
+        self.interrupt(vector);
+        if self.halted() {
+            return Ok((None, true));
         }
+        return Ok((Some(self.stack_pointer_change(sp)), true));
+    }
+
+    /// Pops IP, CS and FLAGS in the reverse order `interrupt` pushed them.
+    fn execute_interrupt_return(&mut self) -> DestinationChange {
+        let sp = self.register(WordRegister::Sp);
+        self.ip = self.pop();
+        let code_segment = self.pop();
+        self.set_segment(SegmentRegister::Cs, code_segment);
+        self.flags.bits = self.pop();
+        self.call_depth = self.call_depth.saturating_sub(1);
+        return self.stack_pointer_change(sp);
+    }
+
+    /// Executes the shifts and rotates one bit at a time, like the 8086 does. The count uses all
+    /// 8 bits of cl, and a count of 0 leaves the flags alone. Rotates only change CF and OF.
+    fn execute_shift(&mut self, instruction: &DecodedArgument) -> Result<DestinationChange> {
+        let (destination, source) = binary_operands(instruction)?;
+        let (mask, sign) = width_masks(instruction.width);
+        let value = self.read_instruction_operand(instruction, &destination)?;
+        let count = self.read_instruction_operand(instruction, &source)? as u8;
 
-        RegisterMode::Universal => {
-            destination.high -= match source {
-                Source::Register(reg) => reg.high,
-                Source::Immediate(immed) => {
-                    let high_mask = u16::MAX - 255;
-                    let high_masked = immed & high_mask;
-                    (high_masked >> 8) as u8
+        let mut result = value & mask;
+        let mut carry = self.flags.get(Flags::CARRY);
+        for _ in 0..count {
+            let high = result & sign != 0;
+            let low = result & 1 != 0;
+            result = match instruction.opcode {
+                Opcode::Shl => result << 1,
+                Opcode::Shr => result >> 1,
+                Opcode::Sar => (result >> 1) | (result & sign),
+                Opcode::Rol => (result << 1) | low as u16,
+                Opcode::Ror => (result >> 1) | if low { sign } else { 0 },
+                Opcode::Rcl => (result << 1) | carry as u16,
+                Opcode::Rcr => (result >> 1) | if carry { sign } else { 0 },
+                _ => {
+                    return Err(Error::UnsupportedInstruction {
+                        offset: instruction.offset,
+                        opcode: instruction.opcode,
+                    })
                 }
+            } & mask;
+            carry = match instruction.opcode {
+                Opcode::Shl | Opcode::Rol | Opcode::Rcl => high,
+                _ => low,
             };
+        }
 
-            destination.low -= match source {
-                Source::Register(reg) => reg.low,
-                Source::Immediate(immed) => immed as u8,
+        if count != 0 {
+            let high = result & sign != 0;
+            let second_highest = result & (sign >> 1) != 0;
+            // OF is only defined for a count of 1, but the 8086 sets it on every bit it moves.
+            let overflow = match instruction.opcode {
+                Opcode::Shl | Opcode::Rol | Opcode::Rcl => high != carry,
+                _ => high != second_highest,
             };
+            self.flags.set(Flags::CARRY, carry);
+            self.flags.set(Flags::OVERFLOW, overflow);
+            if matches!(instruction.opcode, Opcode::Shl | Opcode::Shr | Opcode::Sar) {
+                self.flags.set_result_flags(result, instruction.width);
+            }
+        }
+        return self.write_instruction_operand(instruction, &destination, result);
+    }
+
+    fn is_jump_taken(&self, opcode: Opcode) -> bool {
+        let carry = self.flags.get(Flags::CARRY);
+        let parity = self.flags.get(Flags::PARITY);
+        let zero = self.flags.get(Flags::ZERO);
+        let sign = self.flags.get(Flags::SIGN);
+        let overflow = self.flags.get(Flags::OVERFLOW);
+        match opcode {
+            Opcode::Je => zero,
+            Opcode::Jne => !zero,
+            Opcode::Jl => sign != overflow,
+            Opcode::Jnl => sign == overflow,
+            Opcode::Jle => zero || sign != overflow,
+            Opcode::Jnle => !zero && sign == overflow,
+            Opcode::Jb => carry,
+            Opcode::Jnb => !carry,
+            Opcode::Jbe => carry || zero,
+            Opcode::Jnbe => !carry && !zero,
+            Opcode::Jp => parity,
+            Opcode::Jnp => !parity,
+            Opcode::Jo => overflow,
+            Opcode::Jno => !overflow,
+            Opcode::Js => sign,
+            Opcode::Jns => !sign,
+            Opcode::Jcxz => self.register(WordRegister::Cx) == 0,
+            _ => false,
+        }
+    }
+
+    /// Returns whether the jump was taken.
+    fn execute_jump(&mut self, instruction: &DecodedArgument) -> Result<bool> {
+        let target = jump_target(instruction, self.ip)?;
+        let taken = self.is_jump_taken(instruction.opcode);
+        if taken {
+            self.ip = target;
+        }
+        return Ok(taken);
+    }
+
+    /// Returns the change to cx, and whether the loop jumped back.
+    fn execute_loop(&mut self, instruction: &DecodedArgument) -> Result<(DestinationChange, bool)> {
+        let target = jump_target(instruction, self.ip)?;
+        let count = self.register(WordRegister::Cx).wrapping_sub(1);
+        let change = self.set_word(WordRegister::Cx.name(), count);
+
+        let zero = self.flags.get(Flags::ZERO);
+        let taken = match instruction.opcode {
+            Opcode::Loopz => count != 0 && zero,
+            Opcode::Loopnz => count != 0 && !zero,
+            _ => count != 0,
+        };
+        if taken {
+            self.ip = target;
         }
+        return Ok((change, taken));
     }
 
-    set_flags(get_register_value(destination), flags);
+    /// Runs a string instruction on a single element. The source is DS:SI, the destination ES:DI,
+    /// and both step forward by the element size, or backward when DF is set. A segment override
+    /// only applies to the source.
+    fn execute_string_element(
+        &mut self,
+        instruction: &DecodedArgument,
+    ) -> Option<DestinationChange> {
+        let width = instruction.width;
+        let accumulator = match width {
+            Width::Byte => Operand::SubRegister(ByteRegister::Al),
+            Width::Word => Operand::Register(WordRegister::Ax),
+        };
+        let source_segment =
+            self.segment(instruction.segment_override.unwrap_or(SegmentRegister::Ds));
+        let destination_segment = self.segment(SegmentRegister::Es);
+        let si = self.register(WordRegister::Si);
+        let di = self.register(WordRegister::Di);
+
+        let change = match instruction.opcode {
+            Opcode::Movs => {
+                let value = self.memory.read(source_segment, si, width);
+                Some(self.write_string_destination(destination_segment, di, width, value))
+            }
+            Opcode::Cmps => {
+                let source = self.memory.read(source_segment, si, width);
+                let destination = self.memory.read(destination_segment, di, width);
+                self.flags.set_sub_flags(source, destination, width);
+                None
+            }
+            Opcode::Scas => {
+                let value = self.read_operand(&accumulator, width)?;
+                let destination = self.memory.read(destination_segment, di, width);
+                self.flags.set_sub_flags(value, destination, width);
+                None
+            }
+            Opcode::Lods => {
+                let value = self.memory.read(source_segment, si, width);
+                self.write_operand(&accumulator, width, value)
+            }
+            _ => {
+                let value = self.read_operand(&accumulator, width)?;
+                Some(self.write_string_destination(destination_segment, di, width, value))
+            }
+        };
+
+        let size = match width {
+            Width::Byte => 1u16,
+            Width::Word => 2,
+        };
+        let step = if self.flags.get(Flags::DIRECTION) {
+            size.wrapping_neg()
+        } else {
+            size
+        };
+        if matches!(
+            instruction.opcode,
+            Opcode::Movs | Opcode::Cmps | Opcode::Lods
+        ) {
+            self.set_register(WordRegister::Si, si.wrapping_add(step));
+        }
+        if instruction.opcode != Opcode::Lods {
+            self.set_register(WordRegister::Di, di.wrapping_add(step));
+        }
+        return change;
+    }
 
-    return ExecutedOperation {
-        dest_reg: destination_key,
-        dest_start: destination_starting_value,
-        dest_end: get_register_value(destination),
+    fn write_string_destination(
+        &mut self,
+        segment: u16,
+        offset: u16,
+        width: Width,
+        value: u16,
+    ) -> DestinationChange {
+        let start = self.memory.read(segment, offset, width);
+        self.memory.write(segment, offset, width, value);
+        return DestinationChange {
+            destination: "es:[di]".to_string(),
+            start,
+            end: value,
+        };
+    }
+
+    /// Executes a string instruction, once or as long as its repeat prefix says. A repeated
+    /// instruction counts CX down to 0, and cmps and scas also stop once ZF doesn't match the
+    /// prefix. Returns the change of the last element, or of CX when repeated, and the number
+    /// of elements it worked on.
+    fn execute_string(
+        &mut self,
+        instruction: &DecodedArgument,
+    ) -> (Option<DestinationChange>, u32) {
+        let repeat = match instruction.repeat {
+            Some(repeat) => repeat,
+            None => return (self.execute_string_element(instruction), 1),
+        };
+        let count = self.register(WordRegister::Cx);
+        let mut repetitions = 0;
+        while self.register(WordRegister::Cx) != 0 {
+            self.execute_string_element(instruction);
+            let remaining = self.register(WordRegister::Cx) - 1;
+            self.set_register(WordRegister::Cx, remaining);
+            repetitions += 1;
+
+            if matches!(instruction.opcode, Opcode::Cmps | Opcode::Scas) {
+                let zero = self.flags.get(Flags::ZERO);
+                if (repeat == Repeat::Rep && !zero) || (repeat == Repeat::Repne && zero) {
+                    break;
+                }
+            }
+        }
+        let change = DestinationChange {
+            destination: WordRegister::Cx.name().to_string(),
+            start: count,
+            end: self.register(WordRegister::Cx),
+        };
+        return (Some(change), repetitions);
+    }
+}
+
+/// Loads the machine code in `input` at PROGRAM_SEGMENT:0 on top of the given memory and runs it,
+/// decoding every instruction at CS:IP right before executing it. Stops once execution runs past
+/// the end of the program, at a hlt or an interrupt without a handler, or after MAX_STEPS steps.
+pub fn execute_instructions(
+    input: &[u8],
+    memory: Memory,
+    timing: TimingModel,
+) -> Result<SimulationResult> {
+    let mut cpu = Cpu::new(memory, timing);
+    cpu.set_segment(SegmentRegister::Cs, PROGRAM_SEGMENT);
+    cpu.load_program(input);
+    let code_segment = cpu.segment(SegmentRegister::Cs);
+    // Far jumps and calls can leave the program and come back, so only falling off its end
+    // stops the run.
+    let at_end = |cpu: &Cpu| {
+        cpu.segment(SegmentRegister::Cs) == code_segment && cpu.ip as usize == input.len()
     };
+
+    let mut execution_history: Vec<ExecutedOperation> = Vec::new();
+    let mut total_cycles: u64 = 0;
+    while !cpu.halted() && !at_end(&cpu) {
+        if execution_history.len() == MAX_STEPS {
+            break;
+        }
+        // Errors inside the program point into it, like decode errors do.
+        let step = cpu.step().map_err(|error| {
+            let program_start = Memory::physical_address(code_segment, 0);
+            match error.offset().checked_sub(program_start) {
+                Some(offset) if offset < input.len() => error.with_offset(offset),
+                _ => error,
+            }
+        })?;
+        total_cycles += step.cycles.total() as u64;
+        execution_history.push(step);
+    }
+    let halt_reason = if let Some(reason) = cpu.halt_reason {
+        reason
+    } else if at_end(&cpu) {
+        HaltReason::EndOfProgram
+    } else {
+        HaltReason::StepLimit
+    };
+    return Ok(SimulationResult {
+        steps: execution_history,
+        final_status: cpu.registers,
+        final_ip: cpu.ip,
+        final_flags: cpu.flags,
+        final_memory: cpu.memory,
+        total_cycles,
+        unhandled_ports: cpu.io.unhandled().to_vec(),
+        halt_reason,
+    });
 }
-fn set_flags(destination: u16, flags: &mut [u8]) {
-    // For now ZF is at 0, and SF is at 1
-    flags[0] = (destination == 0) as u8;
-    flags[1] = (destination >= 32768) as u8; // Sign bit, we're watching if the highest bit is set to 1
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn register(result: &SimulationResult, name: &str) -> u16 {
+        return get_register_value(&result.final_status[name]);
+    }
+
+    #[test]
+    fn execution_errors_carry_the_offset() {
+        let unknown = [0xb9, 0x03, 0x00, 0xd6];
+        let error = execute_instructions(&unknown, Memory::new(), TimingModel::I8086)
+            .err()
+            .unwrap();
+        assert_eq!(error.offset(), 3);
+
+        // Outside of the program there's no file offset, only the physical address.
+        let far_call = [
+            0x9a, 0x00, 0x01, 0x00, 0x02, // call 512:256
+        ];
+        let mut memory = Memory::new();
+        memory.load(Memory::physical_address(0x200, 0x100), &[0xd6]);
+        let error = execute_instructions(&far_call, memory, TimingModel::I8086)
+            .err()
+            .unwrap();
+        assert_eq!(error.offset(), 0x2100);
+    }
+
+    #[test]
+    fn logical_instructions() {
+        let code = [
+            0xb8, 0xf0, 0x00, // mov ax, 240
+            0x25, 0x3c, 0x0f, // and ax, 3900
+            0x0c, 0x81, // or al, 129
+            0x31, 0xdb, // xor bx, bx
+            0xb9, 0x05, 0x00, // mov cx, 5
+            0xf7, 0xd9, // neg cx
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        assert_eq!(register(&result, "ax"), 0x00b1);
+        assert_eq!(register(&result, "bx"), 0x0000);
+        assert_eq!(register(&result, "cx"), 0xfffb);
+        assert_eq!(result.final_flags.to_string(), "CAS");
+
+        let code = [
+            0xb9, 0xfb, 0xff, // mov cx, -5
+            0x2c, 0x01, // sub al, 1
+            0xf7, 0xd1, // not cx
+            0xf7, 0xc1, 0x03, 0x00, // test cx, 3
+        ];
+        let mut cpu = Cpu::new(Memory::new(), TimingModel::I8086);
+        cpu.load_program(&code);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.flags().to_string(), "CPAS");
+        // not leaves the flags alone.
+        cpu.step().unwrap();
+        assert_eq!(cpu.register(WordRegister::Cx), 0x0004);
+        assert_eq!(cpu.flags().to_string(), "CPAS");
+        // test clears CF, OF and AF.
+        cpu.step().unwrap();
+        assert_eq!(cpu.register(WordRegister::Cx), 0x0004);
+        assert_eq!(cpu.flags().to_string(), "PZ");
+    }
+
+    #[test]
+    fn shifts_and_rotates() {
+        let code = [
+            0xb8, 0x01, 0xc0, // mov ax, -16383
+            0xd1, 0xe0, // shl ax, 1
+            0xb1, 0x04, // mov cl, 4
+            0xd3, 0xe8, // shr ax, cl
+            0xb3, 0x81, // mov bl, -127
+            0xd0, 0xfb, // sar bl, 1
+            0xd0, 0xdb, // rcr bl, 1
+            0xd3, 0xc3, // rol bx, cl
+        ];
+        let mut cpu = Cpu::new(Memory::new(), TimingModel::I8086);
+        cpu.load_program(&code);
+        cpu.step().unwrap();
+
+        let step = cpu.step().unwrap();
+        assert_eq!(cpu.register(WordRegister::Ax), 0x8002);
+        assert_eq!(cpu.flags().to_string(), "CS");
+        assert_eq!(step.cycles.total(), 2);
+
+        cpu.step().unwrap();
+        let step = cpu.step().unwrap();
+        assert_eq!(cpu.register(WordRegister::Ax), 0x0800);
+        assert_eq!(cpu.flags().to_string(), "P");
+        assert_eq!(step.cycles.total(), 8 + 4 * 4);
+
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.register(WordRegister::Bx), 0x00c0);
+        assert_eq!(cpu.flags().to_string(), "CPS");
+
+        // Rotates only touch CF and OF.
+        cpu.step().unwrap();
+        assert_eq!(cpu.register(WordRegister::Bx), 0x00e0);
+        assert_eq!(cpu.flags().to_string(), "PS");
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.register(WordRegister::Bx), 0x0e00);
+        assert_eq!(cpu.flags().to_string(), "PS");
+    }
+
+    #[test]
+    fn shift_overflow() {
+        let code = [
+            0xb8, 0x00, 0x40, // mov ax, 16384
+            0xd1, 0xe0, // shl ax, 1
+            0xb1, 0x00, // mov cl, 0
+            0xd3, 0xe0, // shl ax, cl
+        ];
+        let mut cpu = Cpu::new(Memory::new(), TimingModel::I8086);
+        cpu.load_program(&code);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        // The sign changed, so OF is set.
+        assert_eq!(cpu.register(WordRegister::Ax), 0x8000);
+        assert_eq!(cpu.flags().to_string(), "PSO");
+
+        // Shifting by 0 doesn't touch the flags.
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.register(WordRegister::Ax), 0x8000);
+        assert_eq!(cpu.flags().to_string(), "PSO");
+    }
+
+    #[test]
+    fn multiply_and_divide() {
+        let code = [
+            0xb8, 0x2c, 0x01, // mov ax, 300
+            0xbb, 0xe8, 0x03, // mov bx, 1000
+            0xf7, 0xe3, // mul bx
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        assert_eq!(register(&result, "dx"), 0x0004);
+        assert_eq!(register(&result, "ax"), 0x93e0);
+        assert_eq!(result.final_flags.to_string(), "CO");
+
+        let code = [
+            0xb0, 0xfe, // mov al, -2
+            0xb3, 0x03, // mov bl, 3
+            0xf6, 0xeb, // imul bl
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        assert_eq!(register(&result, "ax"), 0xfffa);
+        assert_eq!(result.final_flags.to_string(), "");
+
+        let code = [
+            0xb8, 0x9c, 0xff, // mov ax, -100
+            0xba, 0xff, 0xff, // mov dx, -1
+            0xb9, 0xf9, 0xff, // mov cx, -7
+            0xf7, 0xf9, // idiv cx
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        assert_eq!(register(&result, "ax"), 14);
+        assert_eq!(register(&result, "dx"), (-2i16) as u16);
+
+        let code = [
+            0xb8, 0x0b, 0x00, // mov ax, 11
+            0xb3, 0x04, // mov bl, 4
+            0xf6, 0xf3, // div bl
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        assert_eq!(register(&result, "ax"), 0x0302);
+    }
+
+    #[test]
+    fn divide_errors_raise_interrupt_0() {
+        let code = [
+            0xb8, 0x00, 0x10, // mov ax, 4096
+            0xb3, 0x02, // mov bl, 2
+            0xf6, 0xf3, // div bl
+        ];
+        let mut cpu = Cpu::new(Memory::new(), TimingModel::I8086);
+        cpu.set_segment(SegmentRegister::Cs, 0x100);
+        cpu.set_segment(SegmentRegister::Ss, 0x300);
+        cpu.set_register(WordRegister::Sp, 0x100);
+        cpu.memory_mut().write(0, 0, Width::Word, 0x0010);
+        cpu.memory_mut().write(0, 2, Width::Word, 0x0200);
+        cpu.set_flags(Flags {
+            bits: Flags::INTERRUPT | Flags::ZERO,
+        });
+        cpu.load_program(&code);
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+
+        // The quotient doesn't fit into al.
+        assert_eq!(cpu.register(WordRegister::Ax), 0x1000);
+        assert_eq!(cpu.segment(SegmentRegister::Cs), 0x200);
+        assert_eq!(cpu.ip(), 0x10);
+        assert_eq!(cpu.flags().to_string(), "Z");
+        assert_eq!(cpu.register(WordRegister::Sp), 0xfa);
+        let memory = cpu.memory();
+        assert_eq!(memory.read(0x300, 0xfa, Width::Word), 7);
+        assert_eq!(memory.read(0x300, 0xfc, Width::Word), 0x100);
+        assert_eq!(
+            memory.read(0x300, 0xfe, Width::Word),
+            Flags::INTERRUPT | Flags::ZERO
+        );
+
+        let code = [
+            0xf6, 0xf3, // div bl
+        ];
+        let mut cpu = Cpu::new(Memory::new(), TimingModel::I8086);
+        cpu.set_segment(SegmentRegister::Cs, 0x100);
+        cpu.load_program(&code);
+        cpu.step().unwrap();
+        // Without a handler, the CPU stops after the instruction.
+        assert_eq!(
+            cpu.halt_reason(),
+            Some(HaltReason::UnhandledInterrupt(DIVIDE_ERROR))
+        );
+        assert_eq!(cpu.segment(SegmentRegister::Cs), 0x100);
+        assert_eq!(cpu.ip(), 2);
+        assert_eq!(cpu.register(WordRegister::Sp), 0);
+    }
+
+    #[test]
+    fn stack_operations() {
+        let code = [
+            0xbc, 0x00, 0x01, // mov sp, 256
+            0xb8, 0x34, 0x12, // mov ax, 4660
+            0x50, // push ax
+            0xbb, 0x78, 0x56, // mov bx, 22136
+            0x53, // push bx
+            0x8f, 0x06, 0x00, 0x10, // pop word [4096]
+            0xff, 0x36, 0x00, 0x10, // push word [4096]
+            0x07, // pop es
+            0x39, 0xc0, // cmp ax, ax
+            0x9c, // pushf
+            0x5a, // pop dx
+            0x9d, // popf
+            0x54, // push sp
+        ];
+        let mut cpu = Cpu::new(Memory::new(), TimingModel::I8086);
+        cpu.load_program(&code);
+        for _ in 0..5 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.register(WordRegister::Sp), 0xfc);
+        assert_eq!(cpu.stack(2), [0x5678, 0x1234]);
+
+        for _ in 0..7 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.memory().read(0, 0x1000, Width::Word), 0x5678);
+        assert_eq!(cpu.segment(SegmentRegister::Es), 0x5678);
+        assert_eq!(cpu.register(WordRegister::Dx), Flags::PARITY | Flags::ZERO);
+        assert_eq!(cpu.flags().bits, 0x1234);
+        assert_eq!(cpu.register(WordRegister::Sp), 0x100);
+
+        // The 8086 pushes the new value of sp.
+        let step = cpu.step().unwrap();
+        assert_eq!(cpu.stack(1), [0xfe]);
+        assert!(!step.stack_wrapped);
+    }
+
+    #[test]
+    fn stack_wrap_around() {
+        let code = [
+            0x50, // push ax
+            0x50, // push ax
+            0x58, // pop ax
+            0x58, // pop ax
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        let wrapped: Vec<bool> = result.steps.iter().map(|step| step.stack_wrapped).collect();
+        assert_eq!(wrapped, [true, false, false, true]);
+        assert_eq!(register(&result, "sp"), 0);
+    }
+
+    #[test]
+    fn near_call_and_return() {
+        let code = [
+            0xbc, 0x00, 0x01, // mov sp, 256
+            0xe8, 0x05, 0x00, // call 5
+            0xb8, 0x01, 0x00, // mov ax, 1
+            0xe3, 0x04, // jcxz 4
+            0xbb, 0x02, 0x00, // mov bx, 2
+            0xc3, // ret
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        let ips: Vec<u16> = result.steps.iter().map(|step| step.ip_start).collect();
+        assert_eq!(ips, [0, 3, 11, 14, 6, 9]);
+        let depths: Vec<u32> = result.steps.iter().map(|step| step.call_depth).collect();
+        assert_eq!(depths, [0, 0, 1, 1, 0, 0]);
+        assert_eq!(register(&result, "ax"), 1);
+        assert_eq!(register(&result, "bx"), 2);
+        assert_eq!(register(&result, "sp"), 0x100);
+        assert_eq!(result.final_memory.read(0, 0xfe, Width::Word), 6);
+    }
+
+    #[test]
+    fn far_and_indirect_calls() {
+        let code = [
+            0xbc, 0x00, 0x01, // mov sp, 256
+            0x50, // push ax
+            0x9a, 0x00, 0x00, 0x00, 0x02, // call 512:0
+            0xff, 0x1e, 0x00, 0x10, // call far [4096]
+            0xbb, 0x00, 0x03, // mov bx, 768
+            0xff, 0xd3, // call bx
+        ];
+        let mut cpu = Cpu::new(Memory::new(), TimingModel::I8086);
+        cpu.set_segment(SegmentRegister::Cs, 0x100);
+        cpu.set_segment(SegmentRegister::Ds, 0x400);
+        cpu.memory_mut().write(0x400, 0x1000, Width::Word, 0x0000);
+        cpu.memory_mut().write(0x400, 0x1002, Width::Word, 0x0200);
+        // retf 2 at 0200:0000
+        cpu.memory_mut().load(0x2000, &[0xca, 0x02, 0x00]);
+        cpu.load_program(&code);
+
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        assert_eq!((cpu.segment(SegmentRegister::Cs), cpu.ip()), (0x200, 0));
+        assert_eq!(cpu.stack(2), [9, 0x100]);
+        assert_eq!(cpu.call_depth(), 1);
+
+        cpu.step().unwrap();
+        assert_eq!((cpu.segment(SegmentRegister::Cs), cpu.ip()), (0x100, 9));
+        assert_eq!(cpu.register(WordRegister::Sp), 0x100);
+        assert_eq!(cpu.call_depth(), 0);
+
+        cpu.step().unwrap();
+        assert_eq!((cpu.segment(SegmentRegister::Cs), cpu.ip()), (0x200, 0));
+        assert_eq!(cpu.stack(2), [13, 0x100]);
+
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        let step = cpu.step().unwrap();
+        assert_eq!((cpu.segment(SegmentRegister::Cs), cpu.ip()), (0x100, 0x300));
+        assert_eq!(cpu.stack(1), [18]);
+        assert_eq!(step.cycles.total(), 16);
+    }
+
+    #[test]
+    fn jump_table() {
+        let code = [
+            0xbe, 0x02, 0x00, // mov si, 2
+            0xff, 0xa4, 0x00, 0x10, // jmp word [si + 4096]
+            0xb8, 0x01, 0x00, // mov ax, 1
+            0xeb, 0x03, // jmp 3
+            0xb8, 0x02, 0x00, // mov ax, 2
+        ];
+        let mut memory = Memory::new();
+        memory.write(0, 0x1000, Width::Word, 7);
+        memory.write(0, 0x1002, Width::Word, 12);
+        let result = execute_instructions(&code, memory, TimingModel::I8086).unwrap();
+        let ips: Vec<u16> = result.steps.iter().map(|step| step.ip_start).collect();
+        assert_eq!(ips, [0, 3, 12]);
+        assert_eq!(register(&result, "ax"), 2);
+
+        let mut memory = Memory::new();
+        memory.write(0, 0x1002, Width::Word, 7);
+        let result = execute_instructions(&code, memory, TimingModel::I8086).unwrap();
+        let ips: Vec<u16> = result.steps.iter().map(|step| step.ip_start).collect();
+        assert_eq!(ips, [0, 3, 7, 10]);
+        assert_eq!(register(&result, "ax"), 1);
+    }
+
+    #[test]
+    fn far_jumps() {
+        let code = [
+            0xea, 0x00, 0x00, 0x00, 0x02, // jmp 512:0
+        ];
+        let mut cpu = Cpu::new(Memory::new(), TimingModel::I8086);
+        cpu.set_segment(SegmentRegister::Cs, 0x100);
+        // jmp far [bx] at 0200:0000
+        cpu.memory_mut().load(0x2000, &[0xff, 0x2f]);
+        cpu.memory_mut().write(0, 0, Width::Word, 0x0010);
+        cpu.memory_mut().write(0, 2, Width::Word, 0x0300);
+        cpu.load_program(&code);
+
+        cpu.step().unwrap();
+        assert_eq!((cpu.segment(SegmentRegister::Cs), cpu.ip()), (0x200, 0));
+        cpu.step().unwrap();
+        assert_eq!((cpu.segment(SegmentRegister::Cs), cpu.ip()), (0x300, 0x10));
+    }
+
+    #[test]
+    fn string_instructions() {
+        let code = [
+            0xbe, 0x00, 0x10, // mov si, 4096
+            0xbf, 0x00, 0x20, // mov di, 8192
+            0xb9, 0x04, 0x00, // mov cx, 4
+            0xf3, 0xa4, // rep movsb
+            0xbf, 0x00, 0x20, // mov di, 8192
+            0xb9, 0x04, 0x00, // mov cx, 4
+            0xb0, 0x03, // mov al, 3
+            0xf2, 0xae, // repne scasb
+            0xbe, 0x00, 0x10, // mov si, 4096
+            0xac, // lodsb
+            0xab, // stosw
+        ];
+        let mut memory = Memory::new();
+        memory.load(0x1000, &[1, 2, 3, 4]);
+        let result = execute_instructions(&code, memory, TimingModel::I8086).unwrap();
+
+        // stosw overwrote the last copied byte.
+        assert_eq!(result.final_memory.dump(0, 0x2000, 0x2002), [1, 2, 3]);
+        assert_eq!(result.steps[3].dest_reg.as_deref(), Some("cx"));
+        assert_eq!(result.steps[3].cycles.base, 9 + 17 * 4);
+        // repne scasb stops on the match, with DI right past it.
+        assert!(result.final_flags.get(Flags::ZERO));
+        assert_eq!(result.steps[7].dest_end, 1);
+        assert_eq!(register(&result, "cx"), 1);
+        assert_eq!(register(&result, "si"), 0x1001);
+        assert_eq!(register(&result, "di"), 0x2005);
+        assert_eq!(result.final_memory.read(0, 0x2003, Width::Word), 0x0001);
+    }
+
+    #[test]
+    fn repeated_compare_runs_backward() {
+        let code = [
+            0xf3, 0xa7, // repe cmpsw
+        ];
+        let mut cpu = Cpu::new(Memory::new(), TimingModel::I8086);
+        // Keep the code clear of the data at ds:1000 and es:1000.
+        cpu.set_segment(SegmentRegister::Cs, 0x800);
+        cpu.set_segment(SegmentRegister::Es, 0x200);
+        cpu.memory_mut().load(0x1000, &[1, 0, 2, 0, 3, 0]);
+        cpu.memory_mut().load(0x3000, &[9, 0, 2, 0, 3, 0]);
+        cpu.set_register(WordRegister::Si, 0x1004);
+        cpu.set_register(WordRegister::Di, 0x1004);
+        cpu.set_register(WordRegister::Cx, 5);
+        let mut flags = cpu.flags();
+        flags.set(Flags::DIRECTION, true);
+        cpu.set_flags(flags);
+        cpu.load_program(&code);
+
+        cpu.step().unwrap();
+        // The third pair differs, so CX stops at 5 - 3.
+        assert_eq!(cpu.register(WordRegister::Cx), 2);
+        assert_eq!(cpu.register(WordRegister::Si), 0x0ffe);
+        assert_eq!(cpu.register(WordRegister::Di), 0x0ffe);
+        // 1 - 9 borrows and is negative.
+        assert!(!cpu.flags().get(Flags::ZERO));
+        assert!(cpu.flags().get(Flags::CARRY));
+        assert!(cpu.flags().get(Flags::SIGN));
+    }
+
+    #[test]
+    fn segment_overrides() {
+        let code = [
+            0x26, 0x8b, 0x07, // mov ax, es:[bx]
+            0x3e, 0x89, 0x46, 0x00, // mov ds:[bp], ax
+            0x8b, 0x4e, 0x00, // mov cx, [bp]
+            0x2e, 0xa4, // cs movsb
+        ];
+        let mut cpu = Cpu::new(Memory::new(), TimingModel::I8086);
+        cpu.set_segment(SegmentRegister::Cs, 0x100);
+        cpu.set_segment(SegmentRegister::Es, 0x200);
+        cpu.set_segment(SegmentRegister::Ss, 0x300);
+        cpu.set_register(WordRegister::Bx, 4);
+        cpu.set_register(WordRegister::Bp, 6);
+        cpu.memory_mut().write(0x200, 4, Width::Word, 0x1234);
+        cpu.memory_mut().write(0x300, 6, Width::Word, 0x5678);
+        cpu.load_program(&code);
+
+        let step = cpu.step().unwrap();
+        assert_eq!(cpu.register(WordRegister::Ax), 0x1234);
+        assert_eq!(step.cycles.effective_address, 5 + 2);
+        cpu.step().unwrap();
+        assert_eq!(cpu.memory().read(0, 6, Width::Word), 0x1234);
+        cpu.step().unwrap();
+        assert_eq!(cpu.register(WordRegister::Cx), 0x5678);
+        // The source of movsb comes from the code itself, the destination stays ES:DI.
+        cpu.step().unwrap();
+        assert_eq!(cpu.memory().read(0x200, 0, Width::Byte), 0x26);
+    }
+
+    #[test]
+    fn data_transfers() {
+        let code = [
+            0xb8, 0x34, 0x12, // mov ax, 4660
+            0xbb, 0x00, 0x10, // mov bx, 4096
+            0x93, // xchg ax, bx
+            0x86, 0xc4, // xchg al, ah
+            0x8d, 0x70, 0xfe, // lea si, [bx + si - 2]
+            0xc4, 0x3e, 0x00, 0x20, // les di, [8192]
+            0xb8, 0x02, 0x00, // mov ax, 2
+            0xd7, // xlat
+            0x98, // cbw
+            0x99, // cwd
+        ];
+        let mut memory = Memory::new();
+        memory.load(0x1234, &[0x11, 0x22, 0x83]);
+        memory.write(0, 0x2000, Width::Word, 0x5678);
+        memory.write(0, 0x2002, Width::Word, 0x0300);
+        let result = execute_instructions(&code, memory, TimingModel::I8086).unwrap();
+
+        assert_eq!(result.steps[3].dest_end, 0x0010);
+        assert_eq!(register(&result, "bx"), 0x1234);
+        assert_eq!(register(&result, "si"), 0x1232);
+        assert_eq!(register(&result, "di"), 0x5678);
+        assert_eq!(register(&result, "es"), 0x0300);
+        // xlat loads [bx + 2], which cbw and cwd sign extend.
+        assert_eq!(register(&result, "ax"), 0xff83);
+        assert_eq!(register(&result, "dx"), 0xffff);
+        assert_eq!(result.steps[4].cycles.base, 2);
+    }
+
+    #[test]
+    fn flags_through_ah() {
+        let code = [
+            0xb8, 0x01, 0x00, // mov ax, 1
+            0x2d, 0x02, 0x00, // sub ax, 2
+            0x9f, // lahf
+            0x25, 0xff, 0x00, // and ax, 255
+            0x9e, // sahf
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        // lahf copied CF, PF, AF and SF of the sub, sahf the cleared ah back.
+        assert_eq!(result.steps[2].dest_end, 0x95ff);
+        assert_eq!(register(&result, "ax"), 0x00ff);
+        assert_eq!(result.final_flags.bits & 0xff, 0);
+    }
+
+    #[test]
+    fn multi_word_arithmetic() {
+        let code = [
+            0xb8, 0xff, 0xff, // mov ax, 65535
+            0xba, 0x01, 0x00, // mov dx, 1
+            0x05, 0x01, 0x00, // add ax, 1
+            0x83, 0xd2, 0x00, // adc dx, 0
+            0x2d, 0x02, 0x00, // sub ax, 2
+            0x83, 0xda, 0x00, // sbb dx, 0
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        assert_eq!(result.steps[3].dest_end, 2);
+        // 0x2_0000 - 2 = 0x1_fffe
+        assert_eq!(register(&result, "dx"), 1);
+        assert_eq!(register(&result, "ax"), 0xfffe);
+        assert!(!result.final_flags.get(Flags::CARRY));
+    }
+
+    #[test]
+    fn increments_keep_the_carry() {
+        let code = [
+            0xb0, 0xff, // mov al, 255
+            0x04, 0x01, // add al, 1
+            0xfe, 0xc0, // inc al
+            0x48, // dec ax
+            0x48, // dec ax
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        assert_eq!(register(&result, "ax"), 0xffff);
+        assert!(result.final_flags.get(Flags::CARRY));
+        assert!(result.final_flags.get(Flags::SIGN));
+        assert!(!result.final_flags.get(Flags::ZERO));
+        assert_eq!(result.steps[2].cycles.base, 3);
+        assert_eq!(result.steps[3].cycles.base, 2);
+    }
+
+    #[test]
+    fn software_interrupts() {
+        let code = [
+            0xcd, 0x21, // int 33
+            0xce, // into
+            0xcc, // int3
+        ];
+        let mut cpu = Cpu::new(Memory::new(), TimingModel::I8086);
+        cpu.set_segment(SegmentRegister::Cs, 0x100);
+        cpu.set_segment(SegmentRegister::Ss, 0x300);
+        cpu.set_register(WordRegister::Sp, 0x100);
+        let mut flags = cpu.flags();
+        flags.set(Flags::INTERRUPT, true);
+        flags.set(Flags::TRAP, true);
+        cpu.set_flags(flags);
+        // mov ah, 76; iret at 0200:0000, int3 lands at 0200:0010.
+        cpu.memory_mut().load(0x2000, &[0xb4, 0x4c, 0xcf]);
+        cpu.memory_mut().write(0, 0x21 * 4, Width::Word, 0x0000);
+        cpu.memory_mut().write(0, 0x21 * 4 + 2, Width::Word, 0x0200);
+        cpu.memory_mut().write(0, 3 * 4, Width::Word, 0x0010);
+        cpu.memory_mut().write(0, 3 * 4 + 2, Width::Word, 0x0200);
+        cpu.load_program(&code);
+
+        let step = cpu.step().unwrap();
+        assert_eq!((cpu.segment(SegmentRegister::Cs), cpu.ip()), (0x200, 0));
+        assert_eq!(cpu.stack(3), [0x0002, 0x0100, flags.bits]);
+        assert!(!cpu.flags().get(Flags::INTERRUPT));
+        assert!(!cpu.flags().get(Flags::TRAP));
+        assert_eq!(cpu.call_depth(), 1);
+        assert_eq!(step.cycles.base, 51);
+
+        cpu.step().unwrap();
+        let step = cpu.step().unwrap();
+        assert_eq!(step.call_depth, 1);
+        assert_eq!((cpu.segment(SegmentRegister::Cs), cpu.ip()), (0x100, 2));
+        assert_eq!(cpu.register(WordRegister::Ax), 0x4c00);
+        assert_eq!(cpu.register(WordRegister::Sp), 0x100);
+        assert_eq!(cpu.flags(), flags);
+        assert_eq!(cpu.call_depth(), 0);
+
+        // OF is clear, so into falls through.
+        let step = cpu.step().unwrap();
+        assert_eq!(step.ip_end, 3);
+        assert_eq!(step.cycles.base, 4);
+        cpu.step().unwrap();
+        assert_eq!((cpu.segment(SegmentRegister::Cs), cpu.ip()), (0x200, 0x10));
+        assert_eq!(cpu.stack(1), [0x0004]);
+    }
+
+    #[test]
+    fn port_io_goes_to_devices() {
+        use crate::io::Device;
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        // Answers reads with its port number, and records all writes.
+        struct FakeDevice {
+            writes: Rc<RefCell<Vec<(u16, Width, u16)>>>,
+        }
+        impl Device for FakeDevice {
+            fn read(&mut self, port: u16, _width: Width) -> u16 {
+                return port;
+            }
+            fn write(&mut self, port: u16, width: Width, value: u16) {
+                self.writes.borrow_mut().push((port, width, value));
+            }
+        }
+
+        let code = [
+            0xba, 0xf8, 0x03, // mov dx, 1016
+            0xed, // in ax, dx
+            0xe6, 0x61, // out 97, al
+            0xb8, 0x34, 0x12, // mov ax, 4660
+            0xef, // out dx, ax
+            0xe4, 0x10, // in al, 16
+        ];
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        let mut cpu = Cpu::new(Memory::new(), TimingModel::I8086);
+        cpu.io_mut().attach(
+            0x3f8..=0x3ff,
+            Box::new(FakeDevice {
+                writes: writes.clone(),
+            }),
+        );
+        cpu.load_program(&code);
+        while (cpu.ip() as usize) < code.len() {
+            cpu.step().unwrap();
+        }
+
+        assert_eq!(*writes.borrow(), [(0x3f8, Width::Word, 0x1234)]);
+        // Port 16 has no device, so it reads as all ones.
+        assert_eq!(cpu.register(WordRegister::Ax), 0x12ff);
+        assert_eq!(
+            cpu.io().unhandled(),
+            [
+                PortAccess::Write {
+                    port: 0x61,
+                    width: Width::Byte,
+                    value: 0xf8
+                },
+                PortAccess::Read {
+                    port: 0x10,
+                    width: Width::Byte
+                },
+            ]
+        );
+        assert_eq!(
+            cpu.io().unhandled()[0].to_string(),
+            "out byte f8 to port 61"
+        );
+    }
+
+    #[test]
+    fn processor_control() {
+        let code = [
+            0xf9, // stc
+            0xf5, // cmc
+            0xf5, // cmc
+            0xfd, // std
+            0xfb, // sti
+            0x90, // nop
+            0xf4, // hlt
+            0xb8, 0x01, 0x00, // mov ax, 1
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        assert_eq!(result.halt_reason, HaltReason::Hlt);
+        assert_eq!(result.steps.len(), 7);
+        assert_eq!(result.final_ip, 7);
+        assert_eq!(register(&result, "ax"), 0);
+        assert_eq!(result.final_flags.to_string(), "CID");
+        assert_eq!(result.total_cycles, 6 * 2 + 3);
+
+        let result = execute_instructions(&code[7..], Memory::new(), TimingModel::I8086).unwrap();
+        assert_eq!(result.halt_reason, HaltReason::EndOfProgram);
+    }
+
+    #[test]
+    fn far_calls_leave_the_program() {
+        let code = [
+            0x9a, 0x00, 0x01, 0x00, 0x02, // call 512:256
+            0xbb, 0x02, 0x00, // mov bx, 2
+        ];
+        let mut memory = Memory::new();
+        memory.load(
+            Memory::physical_address(0x200, 0x100),
+            &[
+                0xb8, 0x01, 0x00, // mov ax, 1
+                0xcb, // retf
+            ],
+        );
+        let result = execute_instructions(&code, memory, TimingModel::I8086).unwrap();
+        assert_eq!(result.halt_reason, HaltReason::EndOfProgram);
+        assert_eq!(result.steps.len(), 4);
+        assert_eq!(result.steps[1].call_depth, 1);
+        assert_eq!(register(&result, "ax"), 1);
+        assert_eq!(register(&result, "bx"), 2);
+    }
+
+    #[test]
+    fn far_jumps_past_the_end_of_the_program() {
+        let code = [
+            0xea, 0x00, 0x01, 0x00, 0x10, // jmp 4096:256
+        ];
+        let mut memory = Memory::new();
+        memory.load(
+            Memory::physical_address(PROGRAM_SEGMENT, 0x100),
+            &[
+                0xb8, 0x01, 0x00, // mov ax, 1
+                0xf4, // hlt
+            ],
+        );
+        let result = execute_instructions(&code, memory, TimingModel::I8086).unwrap();
+        assert_eq!(result.halt_reason, HaltReason::Hlt);
+        assert_eq!(result.final_ip, 0x104);
+        assert_eq!(register(&result, "ax"), 1);
+
+        let code = [
+            0xff, 0x2e, 0x00, 0x02, // jmp far [512]
+        ];
+        let mut memory = Memory::new();
+        memory.write(0, 0x200, Width::Word, 0x0300);
+        memory.write(0, 0x202, Width::Word, PROGRAM_SEGMENT);
+        memory.load(
+            Memory::physical_address(PROGRAM_SEGMENT, 0x300),
+            &[
+                0xb8, 0x02, 0x00, // mov ax, 2
+                0xf4, // hlt
+            ],
+        );
+        let result = execute_instructions(&code, memory, TimingModel::I8086).unwrap();
+        assert_eq!(result.halt_reason, HaltReason::Hlt);
+        assert_eq!(result.final_ip, 0x304);
+        assert_eq!(register(&result, "ax"), 2);
+    }
+
+    #[test]
+    fn endless_loops_hit_the_step_limit() {
+        let code = [
+            0xeb, 0xfe, // jmp $
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        assert_eq!(result.halt_reason, HaltReason::StepLimit);
+        assert_eq!(result.steps.len(), MAX_STEPS);
+        assert_eq!(result.final_ip, 0);
+    }
+
+    const CONDITIONAL_JUMPS: [Opcode; 10] = [
+        Opcode::Je,
+        Opcode::Jne,
+        Opcode::Jl,
+        Opcode::Jnl,
+        Opcode::Jle,
+        Opcode::Jnle,
+        Opcode::Jb,
+        Opcode::Jnb,
+        Opcode::Jbe,
+        Opcode::Jnbe,
+    ];
+
+    // The conditional jumps that are taken after cmp ax, bx.
+    fn jumps_taken_after_cmp(ax: u16, bx: u16) -> Vec<Opcode> {
+        let code = [
+            0x39, 0xd8, // cmp ax, bx
+        ];
+        let mut cpu = Cpu::new(Memory::new(), TimingModel::I8086);
+        cpu.set_register(WordRegister::Ax, ax);
+        cpu.set_register(WordRegister::Bx, bx);
+        cpu.load_program(&code);
+        cpu.step().unwrap();
+        return CONDITIONAL_JUMPS
+            .into_iter()
+            .filter(|opcode| cpu.is_jump_taken(*opcode))
+            .collect();
+    }
+
+    #[test]
+    fn conditional_jump_predicates() {
+        use Opcode::*;
+        assert_eq!(jumps_taken_after_cmp(5, 5), [Je, Jnl, Jle, Jnb, Jbe]);
+        // 32767 > -32768, but 0x7fff < 0x8000.
+        assert_eq!(
+            jumps_taken_after_cmp(0x7fff, 0x8000),
+            [Jne, Jnl, Jnle, Jb, Jbe]
+        );
+        assert_eq!(
+            jumps_taken_after_cmp(0x8000, 0x7fff),
+            [Jne, Jl, Jle, Jnb, Jnbe]
+        );
+        // -1 < 0, but 0xffff > 0.
+        assert_eq!(jumps_taken_after_cmp(0xffff, 0), [Jne, Jl, Jle, Jnb, Jnbe]);
+        assert_eq!(jumps_taken_after_cmp(0, 1), [Jne, Jl, Jle, Jb, Jbe]);
+        assert_eq!(
+            jumps_taken_after_cmp(0x8000, 0x8000),
+            [Je, Jnl, Jle, Jnb, Jbe]
+        );
+
+        // 0x7fff - -1 overflows to 0x8000, whose low byte has an even number of ones.
+        let code = [
+            0x39, 0xd8, // cmp ax, bx
+        ];
+        let mut cpu = Cpu::new(Memory::new(), TimingModel::I8086);
+        cpu.set_register(WordRegister::Ax, 0x7fff);
+        cpu.set_register(WordRegister::Bx, 0xffff);
+        cpu.load_program(&code);
+        cpu.step().unwrap();
+        assert!(cpu.is_jump_taken(Jo) && cpu.is_jump_taken(Js) && cpu.is_jump_taken(Jp));
+        assert!(!cpu.is_jump_taken(Jno) && !cpu.is_jump_taken(Jns) && !cpu.is_jump_taken(Jnp));
+    }
+
+    #[test]
+    fn loops() {
+        let code = [
+            0xb9, 0x03, 0x00, // mov cx, 3
+            0x40, // label_0: inc ax
+            0xe2, 0xfd, // loop label_0
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        assert_eq!(register(&result, "ax"), 3);
+        assert_eq!(register(&result, "cx"), 0);
+
+        let code = [
+            0xb9, 0x05, 0x00, // mov cx, 5
+            0x40, // label_0: inc ax
+            0xa9, 0x04, 0x00, // test ax, 4
+            0xe1, 0xfa, // loopz label_0
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        assert_eq!(register(&result, "ax"), 4);
+        assert_eq!(register(&result, "cx"), 1);
+
+        let code = [
+            0xb9, 0x05, 0x00, // mov cx, 5
+            0x40, // label_0: inc ax
+            0x3d, 0x02, 0x00, // cmp ax, 2
+            0xe0, 0xfa, // loopnz label_0
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        assert_eq!(register(&result, "ax"), 2);
+        assert_eq!(register(&result, "cx"), 3);
+
+        let code = [
+            0x31, 0xc9, // xor cx, cx
+            0xe3, 0x03, // jcxz label_0
+            0xb8, 0x01, 0x00, // mov ax, 1
+            0x41, // label_0: inc cx
+            0xe3, 0x03, // jcxz to the end
+            0xbb, 0x02, 0x00, // mov bx, 2
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        assert_eq!(register(&result, "ax"), 0);
+        assert_eq!(register(&result, "bx"), 2);
+        assert_eq!(register(&result, "cx"), 1);
+    }
+
+    #[test]
+    fn decimal_adjust() {
+        let code = [
+            0xb0, 0x38, // mov al, 56
+            0x04, 0x45, // add al, 69
+            0x27, // daa
+            0x2c, 0x38, // sub al, 56
+            0x2f, // das
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        // 38 + 45 = 83 and 83 - 38 = 45 in BCD.
+        assert_eq!(result.steps[2].dest_end, 0x83);
+        assert_eq!(register(&result, "ax"), 0x45);
+        assert!(result.final_flags.get(Flags::AUXILIARY_CARRY));
+        assert!(!result.final_flags.get(Flags::CARRY));
+
+        let code = [
+            0xb0, 0x7a, // mov al, 122
+            0x27, // daa
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        // Adding the 6 crosses into the sign bit, which sets the undefined OF.
+        assert_eq!(register(&result, "ax"), 0x80);
+        assert_eq!(result.final_flags.to_string(), "ASO");
+
+        let code = [
+            0xb0, 0x9a, // mov al, 154
+            0x27, // daa
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        assert_eq!(register(&result, "ax"), 0x00);
+        assert_eq!(result.final_flags.to_string(), "CPAZ");
+
+        let code = [
+            0xb0, 0x12, // mov al, 18
+            0x2c, 0x0f, // sub al, 15
+            0x2f, // das
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        // 0x03 - 0x06 borrows out of al, even though the high digit needs no correction.
+        assert_eq!(register(&result, "ax"), 0xfd);
+        assert_eq!(result.final_flags.to_string(), "CAS");
+    }
+
+    #[test]
+    fn ascii_adjust() {
+        let code = [
+            0xb8, 0x08, 0x00, // mov ax, 8
+            0x04, 0x09, // add al, 9
+            0x37, // aaa
+            0x2c, 0x09, // sub al, 9
+            0x3f, // aas
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        // 8 + 9 = 17 and 17 - 9 = 8, as unpacked BCD in ah and al.
+        assert_eq!(result.steps[2].dest_end, 0x0107);
+        assert_eq!(register(&result, "ax"), 0x0008);
+        assert!(result.final_flags.get(Flags::CARRY));
+        assert!(result.final_flags.get(Flags::AUXILIARY_CARRY));
+
+        let code = [
+            0xb0, 0x3f, // mov al, 63
+            0xd4, 0x0a, // aam 10
+            0xd5, 0x0a, // aad 10
+            0xd4, 0x10, // aam 16
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        assert_eq!(result.steps[1].dest_end, 0x0603);
+        assert_eq!(result.steps[2].dest_end, 0x003f);
+        assert_eq!(register(&result, "ax"), 0x030f);
+        assert_eq!(result.final_flags.to_string(), "P");
+        assert_eq!(result.steps[1].cycles.base, 83);
+    }
+
+    #[test]
+    fn ascii_adjust_by_zero_raises_interrupt_0() {
+        let code = [
+            0xd4, 0x00, // aam 0
+        ];
+        let mut cpu = Cpu::new(Memory::new(), TimingModel::I8086);
+        cpu.set_segment(SegmentRegister::Cs, 0x100);
+        cpu.set_register(WordRegister::Sp, 0x100);
+        cpu.set_register(WordRegister::Ax, 0x1234);
+        cpu.memory_mut().write(0, 0, Width::Word, 0x0040);
+        cpu.memory_mut().write(0, 2, Width::Word, 0x0200);
+        cpu.load_program(&code);
+
+        let step = cpu.step().unwrap();
+        assert!(step.dest_reg.is_none());
+        assert_eq!((cpu.segment(SegmentRegister::Cs), cpu.ip()), (0x200, 0x40));
+        assert_eq!(cpu.register(WordRegister::Ax), 0x1234);
+    }
+
+    #[test]
+    fn stepping_a_halted_cpu() {
+        let code = [
+            0xf4, // hlt
+            0x40, // inc ax
+        ];
+        let mut cpu = Cpu::new(Memory::new(), TimingModel::I8086);
+        cpu.load_program(&code);
+        cpu.step().unwrap();
+        assert_eq!(cpu.halt_reason(), Some(HaltReason::Hlt));
+        assert_eq!(cpu.step().err(), Some(Error::Halted { offset: 1 }));
+        assert_eq!(cpu.register(WordRegister::Ax), 0);
+
+        cpu.resume();
+        assert!(!cpu.halted());
+        cpu.step().unwrap();
+        assert_eq!(cpu.register(WordRegister::Ax), 1);
+    }
+
+    #[test]
+    fn stepping_a_cpu() {
+        let code = [
+            0xb9, 0x03, 0x00, // mov cx, 3
+            0x01, 0x0e, 0x00, 0x10, // add [4096], cx
+        ];
+        let mut cpu = Cpu::new(Memory::new(), TimingModel::I8086);
+        cpu.set_segment(SegmentRegister::Ds, 0x100);
+        cpu.memory_mut().write(0x100, 0x1000, Width::Word, 0x1234);
+        cpu.load_program(&code);
+
+        let step = cpu.step().unwrap();
+        assert_eq!(step.instruction.opcode, Opcode::Mov);
+        assert_eq!(cpu.register(WordRegister::Cx), 3);
+        assert_eq!(cpu.ip(), 3);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.memory().read(0x100, 0x1000, Width::Word), 0x1237);
+        assert_eq!(cpu.ip(), 7);
+        assert_eq!(cpu.flags(), Flags::default());
+    }
+
+    #[test]
+    fn source_listing() {
+        // source.asm
+        let code = [
+            0xbb, 0x03, 0xf0, // mov bx, -4093
+            0xb9, 0x01, 0x0f, // mov cx, 3841
+            0x29, 0xcb, // sub bx, cx
+            0xbc, 0xe6, 0x03, // mov sp, 998
+            0xbd, 0xe7, 0x03, // mov bp, 999
+            0x39, 0xe5, // cmp bp, sp
+            0x81, 0xc5, 0x03, 0x04, // add bp, 1027
+            0x81, 0xed, 0xea, 0x07, // sub bp, 2026
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+
+        assert_eq!(register(&result, "bx"), 0xe102);
+        assert_eq!(register(&result, "cx"), 0x0f01);
+        assert_eq!(register(&result, "sp"), 0x03e6);
+        assert_eq!(register(&result, "bp"), 0x0000);
+        assert_eq!(result.final_flags.to_string(), "PZ");
+    }
+
+    #[test]
+    fn word_arithmetic_wraps() {
+        let code = [
+            0x81, 0xc3, 0x30, 0x75, // add bx, 30000
+            0x81, 0xc3, 0x10, 0x27, // add bx, 10000
+            0x81, 0xeb, 0x88, 0x13, // sub bx, 5000
+            0x81, 0xeb, 0x88, 0x13, // sub bx, 5000
+            0xbb, 0x01, 0x00, // mov bx, 1
+            0xb9, 0x64, 0x00, // mov cx, 100
+            0x01, 0xcb, // add bx, cx
+            0xba, 0x0a, 0x00, // mov dx, 10
+            0x29, 0xd1, // sub cx, dx
+            0x81, 0xc3, 0x40, 0x9c, // add bx, 40000
+            0x83, 0xc1, 0xa6, // add cx, -90
+            0xbc, 0x63, 0x00, // mov sp, 99
+            0xbd, 0x62, 0x00, // mov bp, 98
+            0x39, 0xe5, // cmp bp, sp
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+
+        assert_eq!(register(&result, "bx"), 0x9ca5);
+        assert_eq!(register(&result, "cx"), 0x0000);
+        assert_eq!(register(&result, "dx"), 0x000a);
+        assert_eq!(register(&result, "sp"), 0x0063);
+        assert_eq!(register(&result, "bp"), 0x0062);
+        assert_eq!(result.final_flags.to_string(), "CPAS");
+    }
+
+    #[test]
+    fn low_byte_carries_into_high_byte() {
+        let code = [
+            0xb8, 0xff, 0x00, // mov ax, 255
+            0x05, 0x01, 0x00, // add ax, 1
+            0xbb, 0x00, 0x01, // mov bx, 256
+            0x83, 0xeb, 0x01, // sub bx, 1
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+
+        assert_eq!(register(&result, "ax"), 0x0100);
+        assert_eq!(register(&result, "bx"), 0x00ff);
+        assert_eq!(result.final_flags.to_string(), "PA");
+    }
+
+    #[test]
+    fn byte_arithmetic_stays_in_its_half() {
+        let code = [
+            0xb8, 0xff, 0x01, // mov ax, 0x01ff
+            0x04, 0x01, // add al, 1
+            0xbb, 0x00, 0x7f, // mov bx, 0x7f00
+            0x80, 0xc7, 0x01, // add bh, 1
+            0xb9, 0x00, 0x01, // mov cx, 0x0100
+            0x80, 0xe9, 0x01, // sub cl, 1
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+
+        assert_eq!(register(&result, "ax"), 0x0100);
+        assert_eq!(register(&result, "bx"), 0x8000);
+        assert_eq!(register(&result, "cx"), 0x01ff);
+        assert_eq!(result.final_flags.to_string(), "CPAS");
+    }
+
+    #[test]
+    fn word_overflow() {
+        let code = [
+            0xb8, 0xff, 0x7f, // mov ax, 0x7fff
+            0x05, 0x01, 0x00, // add ax, 1
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+
+        assert_eq!(register(&result, "ax"), 0x8000);
+        assert_eq!(result.final_flags.to_string(), "PASO");
+    }
+
+    #[test]
+    fn memory_operands() {
+        let code = [
+            0xc7, 0x06, 0xe8, 0x03, 0x01, 0x00, // mov word [1000], 1
+            0xc7, 0x06, 0xea, 0x03, 0x02, 0x00, // mov word [1002], 2
+            0xc7, 0x06, 0xec, 0x03, 0x03, 0x00, // mov word [1004], 3
+            0xc7, 0x06, 0xee, 0x03, 0x04, 0x00, // mov word [1006], 4
+            0xbb, 0xe8, 0x03, // mov bx, 1000
+            0xc7, 0x47, 0x04, 0x0a, 0x00, // mov word [bx + 4], 10
+            0x01, 0x5f, 0x06, // add [bx + 6], bx
+            0x8b, 0x1e, 0xe8, 0x03, // mov bx, [1000]
+            0x8b, 0x0e, 0xea, 0x03, // mov cx, [1002]
+            0x8b, 0x16, 0xec, 0x03, // mov dx, [1004]
+            0x8b, 0x2e, 0xee, 0x03, // mov bp, [1006]
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+
+        assert_eq!(register(&result, "bx"), 1);
+        assert_eq!(register(&result, "cx"), 2);
+        assert_eq!(register(&result, "dx"), 10);
+        assert_eq!(register(&result, "bp"), 1004);
+    }
+
+    #[test]
+    fn bp_addressing_uses_the_stack_segment() {
+        let code = [
+            0xb8, 0x00, 0x01, // mov ax, 0x100
+            0xbd, 0x00, 0x10, // mov bp, 0x1000
+            0x8e, 0xd0, // mov ss, ax
+            0xc6, 0x46, 0x02, 0x07, // mov byte [bp + 2], 7
+            0x80, 0x46, 0x02, 0xfa, // add byte [bp + 2], 250
+            0x8a, 0x46, 0x02, // mov al, [bp + 2]
+            0x8a, 0x26, 0x02, 0x10, // mov ah, [0x1002]
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+
+        assert_eq!(register(&result, "ax"), 0x0001);
+        assert_eq!(result.final_flags.to_string(), "CA");
+    }
+
+    #[test]
+    fn preloaded_memory() {
+        let code = [
+            0xa1, 0x00, 0x20, // mov ax, [0x2000]
+            0x03, 0x06, 0x02, 0x20, // add ax, [0x2002]
+            0xa3, 0x04, 0x20, // mov [0x2004], ax
+        ];
+        let mut memory = Memory::new();
+        memory.load(0x2000, &[0x34, 0x12, 0x01, 0x01]);
+        let result = execute_instructions(&code, memory, TimingModel::I8086).unwrap();
+
+        assert_eq!(register(&result, "ax"), 0x1335);
+        assert_eq!(
+            result.final_memory.dump(0x0200, 0x0004, 0x0005),
+            [0x35, 0x13]
+        );
+    }
+
+    #[test]
+    fn jumps_to_the_next_instruction_are_taken() {
+        let code = [
+            0xb9, 0x02, 0x00, // mov cx, 2
+            0xe2, 0x00, // loop $+2
+            0x31, 0xc0, // xor ax, ax
+            0x74, 0x00, // je $+2
+            0x75, 0x00, // jne $+2
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        let clocks: Vec<u32> = result
+            .steps
+            .iter()
+            .map(|step| step.cycles.total())
+            .collect();
+        assert_eq!(clocks, [4, 17, 3, 16, 4]);
+    }
+
+    #[test]
+    fn cycle_estimates() {
+        let code = [
+            0xbb, 0xe8, 0x03, // mov bx, 1000
+            0xbd, 0xd0, 0x07, // mov bp, 2000
+            0xbe, 0xb8, 0x0b, // mov si, 3000
+            0xbf, 0xa0, 0x0f, // mov di, 4000
+            0x89, 0xd9, // mov cx, bx
+            0xba, 0x0c, 0x00, // mov dx, 12
+            0x8b, 0x16, 0xe8, 0x03, // mov dx, [1000]
+            0x8b, 0x0f, // mov cx, [bx]
+            0x8b, 0x4e, 0x00, // mov cx, [bp]
+            0x89, 0x0c, // mov [si], cx
+            0x89, 0x0d, // mov [di], cx
+            0x8b, 0x8f, 0xe8, 0x03, // mov cx, [bx + 1000]
+            0x8b, 0x8e, 0xe8, 0x03, // mov cx, [bp + 1000]
+            0x89, 0x8c, 0xe8, 0x03, // mov [si + 1000], cx
+            0x89, 0x8d, 0xe8, 0x03, // mov [di + 1000], cx
+            0x01, 0xd1, // add cx, dx
+            0x01, 0x8d, 0xe8, 0x03, // add [di + 1000], cx
+            0x83, 0xc2, 0x32, // add dx, 50
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        let clocks: Vec<u32> = result
+            .steps
+            .iter()
+            .map(|step| step.cycles.total())
+            .collect();
+
+        assert_eq!(
+            clocks,
+            [4, 4, 4, 4, 2, 4, 14, 13, 17, 14, 14, 17, 17, 18, 18, 3, 25, 4]
+        );
+        assert_eq!(result.total_cycles, 196);
+
+        // Every word transfer costs 4 more clocks on the 8088.
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8088).unwrap();
+        assert_eq!(result.total_cycles, 240);
+    }
+
+    #[test]
+    fn unaligned_word_access_and_branch_cycles() {
+        let code = [
+            0xbb, 0x01, 0x00, // mov bx, 1
+            0x8b, 0x0f, // mov cx, [bx]
+            0x8a, 0x0f, // mov cl, [bx]
+            0xb9, 0x02, 0x00, // mov cx, 2
+            0xe2, 0xfe, // loop $
+        ];
+        let result = execute_instructions(&code, Memory::new(), TimingModel::I8086).unwrap();
+        let clocks: Vec<u32> = result
+            .steps
+            .iter()
+            .map(|step| step.cycles.total())
+            .collect();
+
+        assert_eq!(clocks, [4, 17, 13, 4, 17, 5]);
+    }
 }
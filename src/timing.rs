@@ -0,0 +1,342 @@
+use crate::decoder::DecodedArgument;
+use crate::instruction::{ByteRegister, EffectiveAddress, Opcode, Operand, Width, WordRegister};
+
+/// The 8088 has the same timings as the 8086, but its 8 bit bus needs two transfers for every word.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TimingModel {
+    I8086,
+    I8088,
+}
+
+/// Estimated clocks of a single instruction, split the way the 8086 manual lists them.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Cycles {
+    pub base: u32,
+    pub effective_address: u32,
+    // Extra clocks for word transfers the bus has to split in two.
+    pub transfer_penalty: u32,
+}
+impl Cycles {
+    pub fn total(&self) -> u32 {
+        self.base + self.effective_address + self.transfer_penalty
+    }
+}
+
+// The shape of an operand, as far as the timing tables care about.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Kind {
+    Register,
+    Segment,
+    Memory,
+    Immediate,
+    Other,
+}
+impl Kind {
+    fn parse(operand: &Option<Operand>) -> Kind {
+        match operand {
+            Some(Operand::Register(_)) | Some(Operand::SubRegister(_)) => Kind::Register,
+            Some(Operand::Segment(_)) => Kind::Segment,
+            Some(Operand::Memory(_)) => Kind::Memory,
+            Some(Operand::Immediate(_)) => Kind::Immediate,
+            _ => Kind::Other,
+        }
+    }
+}
+
+// A segment override costs another 2 clocks on top of the address calculation.
+pub fn effective_address_clocks(address: &EffectiveAddress) -> u32 {
+    let override_clocks = if address.segment.is_some() { 2 } else { 0 };
+    if address.is_direct() {
+        return 6 + override_clocks;
+    }
+    // [bp] can only be encoded with a displacement, so it always pays for one.
+    let has_displacement = address.displacement != 0
+        || (address.base == Some(WordRegister::Bp) && address.index.is_none());
+    let clocks = match (address.base, address.index) {
+        (Some(WordRegister::Bp), Some(WordRegister::Di))
+        | (Some(WordRegister::Bx), Some(WordRegister::Si)) => 7,
+        (Some(_), Some(_)) => 8,
+        _ => 5,
+    };
+    if has_displacement {
+        return clocks + 4 + override_clocks;
+    }
+    return clocks + override_clocks;
+}
+
+fn is_accumulator(operand: &Option<Operand>) -> bool {
+    matches!(
+        operand,
+        Some(Operand::Register(WordRegister::Ax)) | Some(Operand::SubRegister(ByteRegister::Al))
+    )
+}
+
+// Length of the instruction without its prefixes.
+fn encoded_length(instruction: &DecodedArgument) -> usize {
+    instruction.byte_count - instruction.prefix_count
+}
+
+// The mov accumulator forms (0xa0 - 0xa3) don't have a ModRM byte, so they are 3 bytes long.
+// They include the address calculation in their base clocks.
+fn is_accumulator_mov(instruction: &DecodedArgument) -> bool {
+    let destination = Kind::parse(&instruction.destination);
+    let source = Kind::parse(&instruction.source);
+    return instruction.opcode == Opcode::Mov
+        && encoded_length(instruction) == 3
+        && ((destination == Kind::Memory && is_accumulator(&instruction.source))
+            || (source == Kind::Memory && is_accumulator(&instruction.destination)));
+}
+
+// The accumulator forms with an immediate don't have a ModRM byte, so they are one byte shorter.
+fn is_accumulator_immediate(instruction: &DecodedArgument) -> bool {
+    let byte_count = match instruction.width {
+        Width::Byte => 2,
+        Width::Word => 3,
+    };
+    return encoded_length(instruction) == byte_count
+        && is_accumulator(&instruction.destination)
+        && Kind::parse(&instruction.source) == Kind::Immediate;
+}
+
+// Returns the base clocks and the number of memory transfers.
+fn base_clocks(instruction: &DecodedArgument, jump_taken: bool, repetitions: u32) -> (u32, u32) {
+    let destination = Kind::parse(&instruction.destination);
+    let source = Kind::parse(&instruction.source);
+    match instruction.opcode {
+        Opcode::Mov => {
+            match (destination, source) {
+                _ if is_accumulator_mov(instruction) => (10, 1),
+                (Kind::Register, Kind::Register)
+                | (Kind::Segment, Kind::Register)
+                | (Kind::Register, Kind::Segment) => (2, 0),
+                (Kind::Register, Kind::Memory) | (Kind::Segment, Kind::Memory) => (8, 1),
+                (Kind::Memory, Kind::Register) | (Kind::Memory, Kind::Segment) => (9, 1),
+                (Kind::Register, Kind::Immediate) => (4, 0),
+                (Kind::Memory, Kind::Immediate) => (10, 1),
+                _ => (0, 0),
+            }
+        }
+        Opcode::Xchg => match (destination, source) {
+            _ if encoded_length(instruction) == 1 => (3, 0),
+            (Kind::Register, Kind::Register) => (4, 0),
+            _ => (17, 2),
+        },
+        Opcode::Lea => (2, 0),
+        Opcode::Lds | Opcode::Les => (16, 2),
+        Opcode::Xlat => (11, 1),
+        Opcode::Lahf | Opcode::Sahf => (4, 0),
+        Opcode::Cbw => (2, 0),
+        Opcode::Cwd => (5, 0),
+        // The dx forms don't have a port byte.
+        Opcode::In | Opcode::Out => match encoded_length(instruction) {
+            1 => (8, 1),
+            _ => (10, 1),
+        },
+        Opcode::Add
+        | Opcode::Adc
+        | Opcode::Sub
+        | Opcode::Sbb
+        | Opcode::And
+        | Opcode::Or
+        | Opcode::Xor => {
+            match (destination, source) {
+                (Kind::Register, Kind::Register) => (3, 0),
+                (Kind::Register, Kind::Memory) => (9, 1),
+                (Kind::Memory, Kind::Register) => (16, 2),
+                (Kind::Register, Kind::Immediate) => (4, 0),
+                (Kind::Memory, Kind::Immediate) => (17, 2),
+                _ => (0, 0),
+            }
+        }
+        Opcode::Cmp => match (destination, source) {
+            (Kind::Register, Kind::Register) => (3, 0),
+            (Kind::Register, Kind::Memory) | (Kind::Memory, Kind::Register) => (9, 1),
+            (Kind::Register, Kind::Immediate) => (4, 0),
+            (Kind::Memory, Kind::Immediate) => (10, 1),
+            _ => (0, 0),
+        },
+        Opcode::Test => match (destination, source) {
+            (Kind::Register, Kind::Register) => (3, 0),
+            (Kind::Register, Kind::Memory) | (Kind::Memory, Kind::Register) => (9, 1),
+            _ if is_accumulator_immediate(instruction) => (4, 0),
+            (Kind::Register, Kind::Immediate) => (5, 0),
+            (Kind::Memory, Kind::Immediate) => (11, 1),
+            _ => (0, 0),
+        },
+        // The clocks depend on the operands, these are the lowest ones of the manual's ranges.
+        Opcode::Mul | Opcode::Imul | Opcode::Div | Opcode::Idiv => {
+            let clocks = match (instruction.opcode, instruction.width) {
+                (Opcode::Mul, Width::Byte) => 70,
+                (Opcode::Mul, Width::Word) => 118,
+                (Opcode::Imul, Width::Byte) => 80,
+                (Opcode::Imul, Width::Word) => 128,
+                (Opcode::Div, Width::Byte) => 80,
+                (Opcode::Div, Width::Word) => 144,
+                (_, Width::Byte) => 101,
+                (_, Width::Word) => 165,
+            };
+            match destination {
+                Kind::Memory => (clocks + 6, 1),
+                _ => (clocks, 0),
+            }
+        }
+        Opcode::Inc | Opcode::Dec => match destination {
+            Kind::Memory => (15, 2),
+            _ if encoded_length(instruction) == 1 => (2, 0),
+            _ => (3, 0),
+        },
+        Opcode::Daa | Opcode::Das | Opcode::Aaa | Opcode::Aas => (4, 0),
+        Opcode::Aam => (83, 0),
+        Opcode::Aad => (60, 0),
+        Opcode::Not | Opcode::Neg => match destination {
+            Kind::Memory => (16, 2),
+            _ => (3, 0),
+        },
+        Opcode::Shl
+        | Opcode::Shr
+        | Opcode::Sar
+        | Opcode::Rol
+        | Opcode::Ror
+        | Opcode::Rcl
+        | Opcode::Rcr => match (destination, source) {
+            (Kind::Memory, Kind::Immediate) => (15, 2),
+            (_, Kind::Immediate) => (2, 0),
+            (Kind::Memory, _) => (20 + 4 * repetitions, 2),
+            _ => (8 + 4 * repetitions, 0),
+        },
+        Opcode::Push => match destination {
+            Kind::Memory => (16, 2),
+            Kind::Segment => (10, 1),
+            _ => (11, 1),
+        },
+        Opcode::Pop => match destination {
+            Kind::Memory => (17, 2),
+            _ => (8, 1),
+        },
+        Opcode::Pushf => (10, 1),
+        Opcode::Popf => (8, 1),
+        Opcode::Call => match destination {
+            Kind::Memory => (21, 2),
+            Kind::Register => (16, 1),
+            _ => (19, 1),
+        },
+        Opcode::CallFar => match destination {
+            Kind::Memory => (37, 4),
+            _ => (28, 2),
+        },
+        Opcode::Ret => match destination {
+            Kind::Immediate => (12, 1),
+            _ => (8, 1),
+        },
+        Opcode::Retf => match destination {
+            Kind::Immediate => (17, 2),
+            _ => (18, 2),
+        },
+        Opcode::Jmp => match destination {
+            Kind::Memory => (18, 1),
+            Kind::Register => (11, 0),
+            _ => (15, 0),
+        },
+        Opcode::JmpFar => match destination {
+            Kind::Memory => (24, 2),
+            _ => (15, 0),
+        },
+        // A repeat prefix costs 9 clocks up front, and every element has its own clocks after that.
+        Opcode::Movs | Opcode::Cmps | Opcode::Scas | Opcode::Lods | Opcode::Stos => {
+            let (single, repeated, transfers) = match instruction.opcode {
+                Opcode::Movs => (18, 17, 2),
+                Opcode::Cmps => (22, 22, 2),
+                Opcode::Scas => (15, 15, 1),
+                Opcode::Lods => (12, 13, 1),
+                _ => (11, 10, 1),
+            };
+            match instruction.repeat {
+                Some(_) => (9 + repeated * repetitions, transfers * repetitions),
+                None => (single, transfers),
+            }
+        }
+        Opcode::Clc
+        | Opcode::Stc
+        | Opcode::Cmc
+        | Opcode::Cld
+        | Opcode::Std
+        | Opcode::Cli
+        | Opcode::Sti
+        | Opcode::Hlt => (2, 0),
+        Opcode::Nop | Opcode::Wait => (3, 0),
+        Opcode::Int => (51, 5),
+        Opcode::Int3 => (52, 5),
+        Opcode::Into => {
+            if jump_taken {
+                (53, 5)
+            } else {
+                (4, 0)
+            }
+        }
+        Opcode::Iret => (24, 3),
+        Opcode::Loop => (if jump_taken { 17 } else { 5 }, 0),
+        Opcode::Loopz | Opcode::Jcxz => (if jump_taken { 18 } else { 6 }, 0),
+        Opcode::Loopnz => (if jump_taken { 19 } else { 5 }, 0),
+        Opcode::Je
+        | Opcode::Jne
+        | Opcode::Jl
+        | Opcode::Jnl
+        | Opcode::Jle
+        | Opcode::Jnle
+        | Opcode::Jb
+        | Opcode::Jnb
+        | Opcode::Jbe
+        | Opcode::Jnbe
+        | Opcode::Jp
+        | Opcode::Jnp
+        | Opcode::Jo
+        | Opcode::Jno
+        | Opcode::Js
+        | Opcode::Jns => (if jump_taken { 16 } else { 4 }, 0),
+    }
+}
+
+/// Estimates the clocks of an executed instruction. `memory_offset` is the effective address of its
+/// memory operand, if it has one, and decides whether word transfers are aligned. `repetitions` is how
+/// often the instruction repeated its work, like the bits a shift by cl moved or the elements
+/// a repeated string instruction worked on.
+pub fn instruction_cycles(
+    instruction: &DecodedArgument,
+    model: TimingModel,
+    memory_offset: Option<u16>,
+    jump_taken: bool,
+    repetitions: u32,
+) -> Cycles {
+    let (base, transfers) = base_clocks(instruction, jump_taken, repetitions);
+    // The lock prefix takes 2 clocks of its own.
+    let base = if instruction.lock { base + 2 } else { base };
+
+    let effective_address = if is_accumulator_mov(instruction) {
+        0
+    } else {
+        [instruction.destination, instruction.source]
+            .iter()
+            .flatten()
+            .map(|operand| match operand {
+                Operand::Memory(address) => effective_address_clocks(address),
+                _ => 0,
+            })
+            .sum()
+    };
+
+    let unaligned = match model {
+        TimingModel::I8086 => memory_offset.is_some_and(|offset| offset % 2 == 1),
+        TimingModel::I8088 => true,
+    };
+    let transfer_penalty = if instruction.width == Width::Word && unaligned {
+        4 * transfers
+    } else {
+        0
+    };
+
+    return Cycles {
+        base,
+        effective_address,
+        transfer_penalty,
+    };
+}
@@ -0,0 +1,48 @@
+#![allow(clippy::needless_return)]
+
+use std::env;
+use std::fs;
+use std::process::Command;
+
+// Runs `code` in execute mode and returns what the simulator printed.
+fn execute(name: &str, code: &[u8]) -> String {
+    let path = env::temp_dir().join(format!("sim_8086_{}_{}.bin", name, std::process::id()));
+    fs::write(&path, code).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_sim_8086"))
+        .arg(&path)
+        .arg("execute")
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(output.status.success());
+    return String::from_utf8(output.stdout).unwrap();
+}
+
+#[test]
+fn interrupts_without_a_handler_stop_the_run() {
+    let code = [
+        0xcd, 0x21, // int 33
+        0xf4, // hlt
+    ];
+    let output = execute("int", &code);
+    let trace: Vec<&str> = output.lines().take_while(|line| !line.is_empty()).collect();
+    assert_eq!(
+        trace,
+        ["int 33; clocks: +51 = 51 (51 + 0ea + 0p), ip: 0 --> 2"]
+    );
+    assert!(output.contains("\ncs: 1000\n"));
+    assert!(output.contains("\nsp: 0\n"));
+    assert!(output.ends_with("stopped by: unhandled interrupt 21\n"));
+}
+
+#[test]
+fn divide_errors_without_a_handler_stop_the_run() {
+    let code = [
+        0xb3, 0x00, // mov bl, 0
+        0xf6, 0xf3, // div bl
+    ];
+    let output = execute("div", &code);
+    assert!(output.contains("\ndiv bl; clocks: +80 = 84 (80 + 0ea + 0p), ip: 2 --> 4\n"));
+    assert!(output.contains("\ncs: 1000\n"));
+    assert!(output.ends_with("stopped by: unhandled interrupt 0\n"));
+}